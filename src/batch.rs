@@ -0,0 +1,206 @@
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use tui::layout::Constraint;
+
+use crate::export::{self, CsvWriter, OutputFormat};
+use crate::psi::PressureLine;
+use crate::util::{format_clock_time, format_seconds};
+use crate::{processes, refresh_sources, AppState};
+
+/// cpu usage is the change between two refreshes, so the first snapshot needs
+/// an earlier refresh to compare against
+const FIRST_SAMPLE_DELAY: Duration = Duration::from_millis(500);
+
+/// `--batch`: snapshots on stdout instead of the TUI, like `top -b`. runs
+/// forever if `iterations` is None
+pub fn run(
+    app_state: &mut AppState,
+    format: OutputFormat,
+    iterations: Option<u64>,
+    delay: u64,
+) -> io::Result<()> {
+    refresh_sources(app_state)?;
+    thread::sleep(FIRST_SAMPLE_DELAY);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut csv = CsvWriter::new();
+    // there's no status line here, so zswap errors go to stderr, once each
+    let mut zswap_error = None;
+    for iteration in 0.. {
+        if matches!(iterations, Some(n) if iteration >= n) {
+            break;
+        }
+        if iteration > 0 {
+            thread::sleep(Duration::from_secs(delay));
+        }
+        refresh_sources(app_state)?;
+        if let Some(e) = &app_state.snapshot.system.zswap_error {
+            if zswap_error.as_ref() != Some(e) {
+                eprintln!("ktop: zswap: {}", e);
+            }
+        }
+        zswap_error = app_state.snapshot.system.zswap_error.clone();
+        let written = match format {
+            OutputFormat::Text => write_snapshot(&mut out, app_state),
+            OutputFormat::Json => export::write_json(&mut out, &app_state.snapshot),
+            OutputFormat::Csv => csv.write(&mut out, &app_state.snapshot),
+        };
+        match written.and_then(|_| out.flush()) {
+            Ok(()) => {}
+            // piped into head or similar, which has seen enough
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        if let Some(recorder) = &mut app_state.recorder {
+            recorder.write(&app_state.snapshot)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_snapshot(out: &mut impl Write, app_state: &AppState) -> io::Result<()> {
+    for line in summary_lines(app_state) {
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out)?;
+
+    // same columns as the TUI, padded to the same widths. the last one isn't padded
+    let widths: Vec<Option<usize>> = app_state
+        .headers
+        .iter()
+        .map(|c| match c.constraint() {
+            Constraint::Length(n) | Constraint::Min(n) => Some(n as usize),
+            _ => None,
+        })
+        .collect();
+    let header: Vec<String> = app_state
+        .headers
+        .iter()
+        .map(|c| c.kind.value().to_string())
+        .collect();
+    writeln!(out, "{}", format_row(&header, &widths))?;
+    for row in processes::get_process_vec(&app_state.snapshot.processes, app_state) {
+        let cells: Vec<String> = row
+            .cells
+            .iter()
+            .map(|spans| spans.0.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        writeln!(out, "{}", format_row(&cells, &widths))?;
+    }
+    writeln!(out)
+}
+
+fn format_row(cells: &[String], widths: &[Option<usize>]) -> String {
+    let last = cells.len().saturating_sub(1);
+    cells
+        .iter()
+        .zip(widths)
+        .enumerate()
+        .map(|(i, (cell, width))| match width {
+            Some(width) if i != last => {
+                let cell: String = cell.chars().take(*width).collect();
+                format!("{:1$}", cell, width)
+            }
+            _ => cell.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// the meter area, as text
+fn summary_lines(app_state: &AppState) -> Vec<String> {
+    let size = |kb: u64| bytefmt::format_base2(kb * 1000).replace('B', "");
+    let percent = |used: u64, total: u64| match total {
+        0 => 0.0,
+        total => used as f64 / total as f64 * 100.0,
+    };
+    let system = &app_state.snapshot.system;
+    let load = system.load_average;
+    let cpu = &system.cpu;
+    let rates = &system.vmstat;
+    let mut lines = vec![
+        format!(
+            "ktop - {} up {}, load average: {:.2}, {:.2}, {:.2}",
+            format_clock_time(app_state.snapshot.time),
+            format_seconds(system.uptime),
+            load[0],
+            load[1],
+            load[2]
+        ),
+        format!(
+            "CPU:  {:5.1}%  user {:.1}%  nice {:.1}%  system {:.1}%  iowait {:.1}%  irq {:.1}%  steal {:.1}%",
+            system.cpu_percent,
+            cpu.user * 100.0,
+            cpu.nice * 100.0,
+            cpu.system * 100.0,
+            cpu.iowait * 100.0,
+            cpu.irq * 100.0,
+            cpu.steal * 100.0
+        ),
+        format!(
+            "MEM:  {:5.1}%  {} of {}",
+            percent(system.memory_used, system.memory_total),
+            size(system.memory_used),
+            size(system.memory_total)
+        ),
+        format!(
+            "SWAP: {:5.1}%  {} of {}",
+            percent(system.swap_used, system.swap_total),
+            size(system.swap_used),
+            size(system.swap_total)
+        ),
+        format!(
+            "Swap in: {:.0}/s out: {:.0}/s  Paging in: {:.0}KB/s out: {:.0}KB/s  Faults: {:.0}/s major: {:.0}/s",
+            rates.swap_in,
+            rates.swap_out,
+            rates.page_in,
+            rates.page_out,
+            rates.faults,
+            rates.major_faults
+        ),
+        format!(
+            "Reclaim scan: {:.0}/s steal: {:.0}/s  THP alloc: {:.0}/s split: {:.0}/s  OOM kills: {}",
+            rates.scanned, rates.stolen, rates.thp_alloc, rates.thp_split, rates.oom_kills_total
+        ),
+    ];
+    if let Some(pressure) = &system.pressure {
+        let line = |name: &str, p: &PressureLine| {
+            format!("{} {:.2} {:.2} {:.2}", name, p.avg10, p.avg60, p.avg300)
+        };
+        let mut psi = vec![
+            line("cpu some", &pressure.cpu.some),
+            line("mem some", &pressure.memory.some),
+        ];
+        if let Some(full) = &pressure.memory.full {
+            psi.push(line("mem full", full));
+        }
+        psi.push(line("io some", &pressure.io.some));
+        if let Some(full) = &pressure.io.full {
+            psi.push(line("io full", full));
+        }
+        lines.push(format!("PSI (avg10 avg60 avg300): {}", psi.join("  ")));
+    }
+    if let Some(stats) = &system.zswap {
+        lines.push(format!(
+            "zswap: {} stored pages in a {} pool, {} written back, {} pool limit hits",
+            stats.stored_pages,
+            bytefmt::format_base2(stats.pool_total_size).replace('B', ""),
+            stats.written_back_pages,
+            stats.pool_limit_hit
+        ));
+    }
+    for device in &system.zram {
+        lines.push(format!(
+            "{} {}: {} -> {}, {} used of {}",
+            device.name,
+            device.algorithm,
+            bytefmt::format_base2(device.orig_data_size).replace('B', ""),
+            bytefmt::format_base2(device.compr_data_size).replace('B', ""),
+            bytefmt::format_base2(device.mem_used_total).replace('B', ""),
+            bytefmt::format_base2(device.disk_size).replace('B', "")
+        ));
+    }
+    lines
+}
@@ -0,0 +1,164 @@
+use std::str::FromStr;
+use tui::layout::Constraint;
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ColumnType {
+    PID,
+    NAME,
+    CPU,
+    RUNTIME,
+    MEMORY,
+    MEMORY_SWAP,
+    USER,
+    STATE,
+    PPID,
+    PRIORITY,
+    NICE,
+    NLWP,
+    VIRT,
+    SHR,
+    START,
+    TTY,
+    AFFINITY,
+}
+
+impl ColumnType {
+    pub fn value(&self) -> &str {
+        match *self {
+            ColumnType::PID => "PID",
+            ColumnType::NAME => "NAME",
+            ColumnType::RUNTIME => "TIME",
+            ColumnType::CPU => "CPU%",
+            ColumnType::MEMORY => "MEMORY",
+            ColumnType::MEMORY_SWAP => "SWAP",
+            ColumnType::USER => "USER",
+            ColumnType::STATE => "S",
+            ColumnType::PPID => "PPID",
+            ColumnType::PRIORITY => "PRI",
+            ColumnType::NICE => "NI",
+            ColumnType::NLWP => "NLWP",
+            ColumnType::VIRT => "VIRT",
+            ColumnType::SHR => "SHR",
+            ColumnType::START => "START",
+            ColumnType::TTY => "TTY",
+            ColumnType::AFFINITY => "CPUS",
+        }
+    }
+
+    /// true if the column comes from /proc/[pid]/stat rather than sysinfo
+    pub fn needs_proc_stat(&self) -> bool {
+        matches!(
+            *self,
+            ColumnType::STATE
+                | ColumnType::PRIORITY
+                | ColumnType::NICE
+                | ColumnType::NLWP
+                | ColumnType::SHR
+                | ColumnType::TTY
+        )
+    }
+
+    /// width used when the config doesn't give one. NAME takes whatever is left
+    pub fn default_width(&self) -> Constraint {
+        match *self {
+            ColumnType::PID => Constraint::Length(8),
+            ColumnType::NAME => Constraint::Min(20),
+            ColumnType::RUNTIME => Constraint::Length(9),
+            ColumnType::CPU => Constraint::Length(7),
+            ColumnType::MEMORY => Constraint::Length(8),
+            ColumnType::MEMORY_SWAP => Constraint::Length(8),
+            ColumnType::USER => Constraint::Length(9),
+            ColumnType::STATE => Constraint::Length(2),
+            ColumnType::PPID => Constraint::Length(8),
+            ColumnType::PRIORITY => Constraint::Length(4),
+            ColumnType::NICE => Constraint::Length(4),
+            ColumnType::NLWP => Constraint::Length(5),
+            ColumnType::VIRT => Constraint::Length(8),
+            ColumnType::SHR => Constraint::Length(8),
+            ColumnType::START => Constraint::Length(6),
+            ColumnType::TTY => Constraint::Length(7),
+            ColumnType::AFFINITY => Constraint::Length(8),
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    /// parses the short names used in the config file, e.g. "pid" or "mem"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pid" => Ok(ColumnType::PID),
+            "name" | "command" => Ok(ColumnType::NAME),
+            "cpu" => Ok(ColumnType::CPU),
+            "time" | "runtime" => Ok(ColumnType::RUNTIME),
+            "mem" | "memory" | "res" | "rss" => Ok(ColumnType::MEMORY),
+            "swap" => Ok(ColumnType::MEMORY_SWAP),
+            "user" => Ok(ColumnType::USER),
+            "state" | "s" => Ok(ColumnType::STATE),
+            "ppid" => Ok(ColumnType::PPID),
+            "pri" | "priority" => Ok(ColumnType::PRIORITY),
+            "ni" | "nice" => Ok(ColumnType::NICE),
+            "nlwp" | "threads" => Ok(ColumnType::NLWP),
+            "virt" => Ok(ColumnType::VIRT),
+            "shr" => Ok(ColumnType::SHR),
+            "start" => Ok(ColumnType::START),
+            "tty" => Ok(ColumnType::TTY),
+            "affinity" | "cpus" => Ok(ColumnType::AFFINITY),
+            _ => Err(format!("unknown column `{}`", s)),
+        }
+    }
+}
+
+/// one column of the process table, as picked in the config or with --columns
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Column {
+    pub kind: ColumnType,
+    /// fixed width in cells. None uses the column's default
+    pub width: Option<u16>,
+}
+
+impl Column {
+    pub fn new(kind: ColumnType) -> Self {
+        Column { kind, width: None }
+    }
+
+    pub fn constraint(&self) -> Constraint {
+        match self.width {
+            Some(width) => Constraint::Length(width),
+            None => self.kind.default_width(),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    /// "cpu" or "cpu:10" - the optional number is the width
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        let kind = parts.next().unwrap_or("").parse()?;
+        let width = match parts.next() {
+            None => None,
+            Some(w) => match w.parse::<u16>() {
+                Ok(w) if w > 0 => Some(w),
+                _ => return Err(format!("invalid width `{}` for column `{}`", w, s)),
+            },
+        };
+        Ok(Column { kind, width })
+    }
+}
+
+/// parses a comma separated list like "pid,cpu:6,mem,name"
+pub fn parse_column_list(s: &str) -> Result<Vec<Column>, String> {
+    let columns = s
+        .split(',')
+        .filter(|c| !c.trim().is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<Column>, _>>()?;
+    if columns.is_empty() {
+        return Err(String::from("needs at least one column"));
+    }
+    Ok(columns)
+}
@@ -1,30 +1,413 @@
 use clap::ArgMatches;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::Spanned;
+
+use crate::columns::{parse_column_list, Column, ColumnType};
+use crate::export::OutputFormat;
+
 const DEFAULT_DELAY: u64 = 5;
+const DEFAULT_HISTORY_WINDOW: u64 = 300;
 pub struct AppConfig {
     pub delay: u64,
-    pub run_once: bool,
+    /// print plain text snapshots instead of starting the TUI
+    pub batch: bool,
+    /// how many snapshots to print in batch mode. None means forever
+    pub iterations: Option<u64>,
+    /// anything but text implies batch mode
+    pub format: OutputFormat,
+    /// write every refresh to this file
+    pub record: Option<PathBuf>,
+    /// show a recording instead of this machine
+    pub replay: Option<PathBuf>,
+    /// show a /proc captured from another machine instead of this one
+    pub fixture: Option<PathBuf>,
+    /// run headless, answering `--connect` clients on this address
+    pub serve: Option<String>,
+    /// let `--connect` clients kill, renice and pin processes
+    pub allow_actions: bool,
+    /// a file with the secret `--serve` asks clients for, and `--connect` sends
+    pub token: Option<PathBuf>,
+    /// show a machine running `ktop --serve` instead of this one
+    pub connect: Option<String>,
     pub can_use_debugfs: bool,
-    pub show_threads: bool
+    /// where debugfs is mounted. found from /proc/self/mountinfo if not set
+    pub debugfs_path: Option<PathBuf>,
+    pub show_threads: bool,
+    pub columns: Vec<Column>,
+    pub sort_by: Option<ColumnType>,
+    pub cpu_breakdown: bool,
+    /// start with the graph panel open
+    pub show_graphs: bool,
+    /// how many seconds of history the graphs keep
+    pub history_window: u64,
 }
 
-pub fn create_config_from_matches(matches: ArgMatches)-> AppConfig{
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            delay: DEFAULT_DELAY,
+            batch: false,
+            iterations: None,
+            format: OutputFormat::Text,
+            record: None,
+            replay: None,
+            fixture: None,
+            serve: None,
+            allow_actions: false,
+            token: None,
+            connect: None,
+            can_use_debugfs: false,
+            debugfs_path: None,
+            show_threads: true,
+            columns: vec![
+                Column::new(ColumnType::PID),
+                Column::new(ColumnType::RUNTIME),
+                Column::new(ColumnType::CPU),
+                Column::new(ColumnType::MEMORY),
+                Column::new(ColumnType::MEMORY_SWAP),
+                Column::new(ColumnType::NAME),
+            ],
+            sort_by: Some(ColumnType::CPU),
+            cpu_breakdown: false,
+            show_graphs: false,
+            history_window: DEFAULT_HISTORY_WINDOW,
+        }
+    }
+}
+
+/// Everything that can be set from config.toml. Every key is optional - anything
+/// missing falls back to the defaults in `AppConfig`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    delay: Option<u64>,
+    threads: Option<bool>,
+    zswap: Option<bool>,
+    debugfs_path: Option<PathBuf>,
+    // kept as spanned strings so a bad column name can be reported with its line.
+    // columns are "name" or "name:width"
+    columns: Option<Vec<Spanned<String>>>,
+    sort: Option<Spanned<String>>,
+    cpu_breakdown: Option<bool>,
+    graphs: Option<bool>,
+    /// seconds
+    history: Option<u64>,
+}
 
-    let delay_str = matches.value_of("refresh time");
-    let delay_time = match delay_str {
-        None => DEFAULT_DELAY,
-        Some(s) => match s.parse::<u64>() {
-            Ok(n) => n,
-            Err(_) => {
-                println!("Invalid value passed to refresh time: {}", s);
-                std::process::exit(-1)
+#[derive(Debug)]
+pub enum ConfigError {
+    /// the config file exists but couldn't be read
+    Io(PathBuf, io::Error),
+    /// the config file is not valid toml, or has a bad value. line/column are 1-based
+    Parse {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+    /// a bad value passed on the command line
+    InvalidArgument { name: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => {
+                write!(f, "could not read config file {}: {}", path.display(), e)
             }
-        },
+            ConfigError::Parse {
+                path,
+                position: Some((line, col)),
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, col, message),
+            ConfigError::Parse {
+                path,
+                position: None,
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::InvalidArgument { name, message } => {
+                write!(f, "invalid value passed to {}: {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// $XDG_CONFIG_HOME/ktop/config.toml, falling back to ~/.config/ktop/config.toml
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("ktop").join("config.toml"))
+}
+
+/// 1-based line and column of a byte offset into `contents`
+fn line_col(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, col)
+}
+
+fn parse_column<T: FromStr<Err = String>>(
+    path: &Path,
+    contents: &str,
+    value: &Spanned<String>,
+) -> Result<T, ConfigError> {
+    value.get_ref().parse().map_err(|message| ConfigError::Parse {
+        path: path.to_path_buf(),
+        position: Some(line_col(contents, value.start())),
+        message,
+    })
+}
+
+/// where in a config file the sort column was picked, for errors about it
+/// that can only be found once the command line has been read
+struct SortSetting {
+    path: PathBuf,
+    position: (usize, usize),
+}
+
+/// returns where the file picked the sort column, if it did
+fn apply_config_file(
+    config: &mut AppConfig,
+    path: &Path,
+    required: bool,
+) -> Result<Option<SortSetting>, ConfigError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(s) => s,
+        // only complain about a missing file if the user asked for it
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(None),
+        Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+    };
+    let file_config = parse_config_file(path, &contents)?;
+    if let Some(delay) = file_config.delay {
+        config.delay = delay;
+    }
+    if let Some(threads) = file_config.threads {
+        config.show_threads = threads;
+    }
+    if let Some(zswap) = file_config.zswap {
+        config.can_use_debugfs = zswap;
+    }
+    if let Some(debugfs_path) = file_config.debugfs_path {
+        config.debugfs_path = Some(debugfs_path);
+    }
+    if let Some(cpu_breakdown) = file_config.cpu_breakdown {
+        config.cpu_breakdown = cpu_breakdown;
+    }
+    if let Some(graphs) = file_config.graphs {
+        config.show_graphs = graphs;
+    }
+    if let Some(history) = file_config.history {
+        config.history_window = history;
+    }
+    if let Some(columns) = file_config.columns {
+        if columns.is_empty() {
+            return Err(ConfigError::Parse {
+                path: path.to_path_buf(),
+                position: None,
+                message: String::from("`columns` needs at least one column"),
+            });
+        }
+        config.columns = columns
+            .iter()
+            .map(|c| parse_column(path, &contents, c))
+            .collect::<Result<_, _>>()?;
+    }
+    let mut sort_setting = None;
+    if let Some(sort) = file_config.sort {
+        // "none" leaves the table unsorted
+        config.sort_by = if sort.get_ref().eq_ignore_ascii_case("none") {
+            None
+        } else {
+            Some(parse_column(path, &contents, &sort)?)
+        };
+        sort_setting = Some(SortSetting {
+            path: path.to_path_buf(),
+            position: line_col(&contents, sort.start()),
+        });
+    }
+    Ok(sort_setting)
+}
+
+fn parse_config_file(path: &Path, contents: &str) -> Result<ConfigFile, ConfigError> {
+    toml::from_str(contents).map_err(|e| {
+        // toml appends " at line x column y" to the message - we print that ourselves
+        let message = e.to_string();
+        let message = match message.find(" at line ") {
+            Some(i) => message[..i].to_string(),
+            None => message,
+        };
+        ConfigError::Parse {
+            path: path.to_path_buf(),
+            position: e.line_col().map(|(line, col)| (line + 1, col + 1)),
+            message,
+        }
+    })
+}
+
+pub fn create_config_from_matches(matches: &ArgMatches) -> Result<AppConfig, ConfigError> {
+    let mut config = AppConfig::default();
+
+    // file values first, so CLI flags can override them
+    let file = match matches.value_of("config file") {
+        Some(path) => Some((PathBuf::from(path), true)),
+        None => default_config_path().map(|path| (path, false)),
     };
-    let run_once = matches.is_present("run once");
-    let can_use_debugfs = matches.is_present("zswap") ;//|| cfg!(debug_assertions); // always use zswap when debugging
-    let hide_threads = matches.is_present("hide threads");
+    let mut sort_setting = None;
+    if let Some((path, required)) = file {
+        sort_setting = apply_config_file(&mut config, &path, required)?;
+    }
 
+    if let Some(s) = matches.value_of("refresh time") {
+        config.delay = s.parse::<u64>().map_err(|_| ConfigError::InvalidArgument {
+            name: String::from("refresh time"),
+            message: format!("`{}` is not a whole number of seconds", s),
+        })?;
+    }
+    if let Some(s) = matches.value_of("history") {
+        config.history_window = s.parse::<u64>().map_err(|_| ConfigError::InvalidArgument {
+            name: String::from("history"),
+            message: format!("`{}` is not a whole number of seconds", s),
+        })?;
+    }
+    if let Some(s) = matches.value_of("columns") {
+        config.columns = parse_column_list(s).map_err(|message| ConfigError::InvalidArgument {
+            name: String::from("columns"),
+            message,
+        })?;
+    }
+    if matches.is_present("batch") {
+        config.batch = true;
+    }
+    if let Some(s) = matches.value_of("iterations") {
+        config.iterations = Some(s.parse::<u64>().map_err(|_| ConfigError::InvalidArgument {
+            name: String::from("iterations"),
+            message: format!("`{}` is not a whole number", s),
+        })?);
+    }
+    if let Some(s) = matches.value_of("format") {
+        config.format = s.parse().map_err(|message| ConfigError::InvalidArgument {
+            name: String::from("format"),
+            message,
+        })?;
+        // json and csv are for other programs, so there's no point starting the UI
+        if config.format != OutputFormat::Text {
+            config.batch = true;
+        }
+    }
+    if matches.is_present("run once") {
+        config.batch = true;
+        config.iterations = Some(1);
+    }
+    if let Some(path) = matches.value_of("record") {
+        config.record = Some(PathBuf::from(path));
+    }
+    if let Some(path) = matches.value_of("replay") {
+        config.replay = Some(PathBuf::from(path));
+    }
+    if let Some(path) = matches.value_of("fixture") {
+        config.fixture = Some(PathBuf::from(path));
+    }
+    if let Some(addr) = matches.value_of("serve") {
+        config.serve = Some(addr.to_string());
+    }
+    if matches.is_present("allow actions") {
+        config.allow_actions = true;
+    }
+    if let Some(addr) = matches.value_of("connect") {
+        config.connect = Some(addr.to_string());
+    }
+    if let Some(path) = matches.value_of("token") {
+        if config.serve.is_none() && config.connect.is_none() {
+            return Err(ConfigError::InvalidArgument {
+                name: String::from("token"),
+                message: String::from("it's only used with --serve or --connect"),
+            });
+        }
+        config.token = Some(PathBuf::from(path));
+    }
+    if matches.is_present("cpu breakdown") {
+        config.cpu_breakdown = true;
+    }
+    if matches.is_present("graphs") {
+        config.show_graphs = true;
+    }
+    if matches.is_present("zswap") {
+        config.can_use_debugfs = true;
+    }
+    if let Some(path) = matches.value_of("debugfs path") {
+        config.debugfs_path = Some(PathBuf::from(path));
+    }
     // allow show_threads to override hide_threads
-    let show_threads = !hide_threads || matches.is_present("show threads");
-    AppConfig { delay: delay_time, run_once, can_use_debugfs, show_threads }
-}
\ No newline at end of file
+    if matches.is_present("show threads") {
+        config.show_threads = true;
+    } else if matches.is_present("hide threads") {
+        config.show_threads = false;
+    }
+    // the sort column is the highlighted header, so it has to be one of the columns
+    if let Some(key) = config.sort_by {
+        if !config.columns.iter().any(|c| c.kind == key) {
+            match sort_setting {
+                Some(SortSetting { path, position }) => {
+                    return Err(ConfigError::Parse {
+                        path,
+                        position: Some(position),
+                        message: format!("sort: {} is not one of the columns shown", key.value()),
+                    })
+                }
+                // the default sort column was left out, so sort by the first one instead
+                None => config.sort_by = Some(config.columns[0].kind),
+            }
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use clap::{App, Arg};
+
+    use super::*;
+
+    fn config_from_file(name: &str, contents: &str) -> Result<AppConfig, ConfigError> {
+        let path = env::temp_dir().join(format!(
+            "ktop-config-test-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        let matches = App::new("ktop")
+            .arg(
+                Arg::with_name("config file")
+                    .long("config")
+                    .takes_value(true),
+            )
+            .get_matches_from(vec!["ktop", "--config", path.to_str().unwrap()]);
+        let config = create_config_from_matches(&matches);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn sort_column_not_shown() {
+        let contents = "columns = [\"pid\", \"name\"]\nsort = \"cpu\"\n";
+        match config_from_file("sort", contents) {
+            Err(ConfigError::Parse { position, .. }) => assert_eq!(position, Some((2, 8))),
+            Err(e) => panic!("wrong error: {}", e),
+            Ok(_) => panic!("sorted by a column that isn't shown"),
+        }
+        // without a sort, the first column shown is used
+        let config = config_from_file("no-sort", "columns = [\"pid\", \"name\"]\n").unwrap();
+        assert!(config.sort_by == Some(ColumnType::PID));
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// jiffies spent in each state, from one cpu line of /proc/stat
+#[derive(Clone, Copy, Default)]
+struct CpuTimes {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+}
+
+impl CpuTimes {
+    fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+}
+
+/// share of time spent in each state since the last update, 0 to 1
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct CpuBreakdown {
+    pub user: f32,
+    pub nice: f32,
+    pub system: f32,
+    pub iowait: f32,
+    /// hard and soft irqs
+    pub irq: f32,
+    pub steal: f32,
+}
+
+impl CpuBreakdown {
+    fn between(old: &CpuTimes, new: &CpuTimes) -> Self {
+        let total = new.total().saturating_sub(old.total());
+        if total == 0 {
+            return CpuBreakdown::default();
+        }
+        let share = |new: u64, old: u64| new.saturating_sub(old) as f32 / total as f32;
+        CpuBreakdown {
+            user: share(new.user, old.user),
+            nice: share(new.nice, old.nice),
+            system: share(new.system, old.system),
+            iowait: share(new.iowait, old.iowait),
+            irq: share(new.irq + new.softirq, old.irq + old.softirq),
+            steal: share(new.steal, old.steal),
+        }
+    }
+}
+
+/// per state cpu usage from /proc/stat, which sysinfo doesn't split up
+pub struct CpuStat {
+    pub total: CpuBreakdown,
+    /// one per logical cpu, in order
+    pub cores: Vec<CpuBreakdown>,
+    last_total: CpuTimes,
+    last_cores: Vec<CpuTimes>,
+}
+
+impl CpuStat {
+    pub fn new() -> Self {
+        Self {
+            total: CpuBreakdown::default(),
+            cores: Vec::new(),
+            last_total: CpuTimes::default(),
+            last_cores: Vec::new(),
+        }
+    }
+
+    /// `root` is / for this machine
+    pub fn update(&mut self, root: &Path) {
+        let stat = match fs::read_to_string(root.join("proc/stat")) {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        let mut cores = Vec::new();
+        for line in stat.lines().filter(|l| l.starts_with("cpu")) {
+            let mut fields = line.split_whitespace();
+            let name = fields.next().unwrap_or("");
+            let values: Vec<u64> = fields.filter_map(|v| v.parse().ok()).collect();
+            let value = |i: usize| values.get(i).copied().unwrap_or(0);
+            let times = CpuTimes {
+                user: value(0),
+                nice: value(1),
+                system: value(2),
+                idle: value(3),
+                iowait: value(4),
+                irq: value(5),
+                softirq: value(6),
+                steal: value(7),
+            };
+            if name == "cpu" {
+                self.total = CpuBreakdown::between(&self.last_total, &times);
+                self.last_total = times;
+            } else {
+                cores.push(times);
+            }
+        }
+        self.cores = cores
+            .iter()
+            .enumerate()
+            .map(|(i, times)| match self.last_cores.get(i) {
+                Some(last) => CpuBreakdown::between(last, times),
+                None => CpuBreakdown::default(),
+            })
+            .collect();
+        self.last_cores = cores;
+    }
+}
+
+impl Default for CpuStat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
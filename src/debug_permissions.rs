@@ -1,32 +1,118 @@
-use std::fs::File;
+use std::ffi::OsString;
+use std::fs::{self, File};
 use std::io::ErrorKind;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 pub enum DebugfsStatus {
     NotMounted,
     NoPermissions,
     MountedAndReadable,
 }
-pub const DEBUG_DIR: &str = "/sys/kernel/debug";
-// TODO: allow custom debugfs location
-pub fn can_read_debug() -> DebugfsStatus {
-    match File::open(DEBUG_DIR) {
+pub const DEFAULT_DEBUG_DIR: &str = "/sys/kernel/debug";
+
+/// where debugfs is mounted, from /proc/self/mountinfo. if it's mounted more
+/// than once, the usual place wins
+pub fn find_debugfs() -> Option<PathBuf> {
+    let mounts = debugfs_mounts();
+    mounts
+        .iter()
+        .find(|m| m.as_path() == Path::new(DEFAULT_DEBUG_DIR))
+        .or_else(|| mounts.first())
+        .cloned()
+}
+
+/// whether debugfs is mounted at `path`
+pub fn is_debugfs_mount(path: &Path) -> bool {
+    debugfs_mounts().iter().any(|m| m.as_path() == path)
+}
+
+/// every debugfs mount point in /proc/self/mountinfo. read as bytes, since
+/// mount points don't have to be utf-8
+fn debugfs_mounts() -> Vec<PathBuf> {
+    fs::read("/proc/self/mountinfo")
+        .map(|mountinfo| {
+            mountinfo
+                .split(|b| *b == b'\n')
+                .filter_map(debugfs_mount_point)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// the mount point, if this mountinfo line is a debugfs mount:
+/// 36 25 0:7 / /sys/kernel/debug rw,nosuid,nodev,noexec,relatime shared:15 - debugfs debugfs rw
+fn debugfs_mount_point(line: &[u8]) -> Option<PathBuf> {
+    let fields: Vec<&[u8]> = line.split(|b| *b == b' ').collect();
+    // a variable number of optional fields come before the " - " separator
+    let separator = fields.iter().position(|f| *f == b"-")?;
+    match fields.get(separator + 1) {
+        Some(&b"debugfs") => fields.get(4).map(|m| unescape_mount_point(m)),
+        _ => None,
+    }
+}
+
+/// spaces and such in mount points are written as octal escapes, like \040.
+/// anything else, utf-8 or not, is left as it is
+fn unescape_mount_point(s: &[u8]) -> PathBuf {
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let escaped = match s[i] {
+            b'\\' => s
+                .get(i + 1..i + 4)
+                .and_then(|o| std::str::from_utf8(o).ok())
+                .and_then(|o| u8::from_str_radix(o, 8).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 4;
+            }
+            None => {
+                out.push(s[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(out))
+}
+
+pub fn can_read_debug(debug_dir: &Path) -> DebugfsStatus {
+    match File::open(debug_dir) {
         Err(e) => match e.kind() {
             ErrorKind::PermissionDenied => DebugfsStatus::NoPermissions,
             ErrorKind::NotFound => DebugfsStatus::NotMounted,
-            _ => DebugfsStatus::NotMounted
+            _ => DebugfsStatus::NotMounted,
         },
         Ok(_) => DebugfsStatus::MountedAndReadable,
     }
 }
 
-// TODO: don't run a command like that (start a root-level process and use std::os::unix::fs::PermissionsExt ?)
-pub fn set_debug_permissions() -> bool {
-    // TODO: print a warning or explaination as to whats happening
-    println!("Trying to get permissions for debugfs");
-    ::std::process::Command::new("sudo")
-        .arg("/usr/bin/chmod")
-        .arg("755")
-        .arg(DEBUG_DIR)
-        .status()
-        .unwrap()
-        .success()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_points() {
+        let line = "36 25 0:7 / /mnt/d\\040bug\\134fs rw,relatime shared:15 - debugfs debugfs rw";
+        assert_eq!(
+            debugfs_mount_point(line.as_bytes()),
+            Some(PathBuf::from("/mnt/d bug\\fs"))
+        );
+        // not utf-8, and kept byte for byte
+        let mut line = b"36 25 0:7 / /mnt/d\xe9bug - debugfs debugfs rw".to_vec();
+        let path = debugfs_mount_point(&line).unwrap();
+        assert_eq!(
+            path,
+            PathBuf::from(OsString::from_vec(b"/mnt/d\xe9bug".to_vec()))
+        );
+        line = b"36 25 0:7 / /mnt/d\xc3\xa9bug - debugfs debugfs rw".to_vec();
+        assert_eq!(
+            debugfs_mount_point(&line),
+            Some(PathBuf::from("/mnt/d\u{e9}bug"))
+        );
+        let line = "22 1 0:21 / /proc rw - proc proc rw";
+        assert_eq!(debugfs_mount_point(line.as_bytes()), None);
+    }
 }
@@ -0,0 +1,205 @@
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    Frame,
+};
+
+use crate::process_actions::SIGNALS;
+use crate::processes::RowId;
+use crate::util::{centered_rect, StatefulList};
+
+/// popups that take over the keyboard until they're closed
+pub enum Dialog {
+    /// pick a signal to send to `target`
+    SignalPicker {
+        target: RowId,
+        name: String,
+        signals: StatefulList<(&'static str, i32)>,
+    },
+    /// last chance before sending `signal`
+    ConfirmSignal {
+        target: RowId,
+        name: String,
+        signal: (&'static str, i32),
+    },
+    /// one checkbox per cpu
+    Affinity {
+        target: RowId,
+        name: String,
+        cpus: StatefulList<bool>,
+    },
+}
+
+/// what the main loop should do after a key went to the dialog
+pub enum DialogAction {
+    /// keep the dialog open
+    None,
+    Close,
+    /// send the signal, then close
+    SendSignal(RowId, i32),
+    /// apply the new affinity, then close
+    SetAffinity(RowId, Vec<bool>),
+}
+
+impl Dialog {
+    pub fn signal_picker(target: RowId, name: String) -> Self {
+        let mut signals = StatefulList::with_items(SIGNALS.to_vec());
+        signals.state.select(Some(0));
+        Dialog::SignalPicker {
+            target,
+            name,
+            signals,
+        }
+    }
+
+    pub fn affinity(target: RowId, name: String, allowed: Vec<bool>) -> Self {
+        let mut cpus = StatefulList::with_items(allowed);
+        cpus.state.select(Some(0));
+        Dialog::Affinity { target, name, cpus }
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> DialogAction {
+        match self {
+            Dialog::SignalPicker {
+                target,
+                name,
+                signals,
+            } => match key {
+                Key::Esc | Key::Char('q') => DialogAction::Close,
+                Key::Down | Key::Char('j') => {
+                    signals.next();
+                    DialogAction::None
+                }
+                Key::Up | Key::Char('k') => {
+                    signals.previous();
+                    DialogAction::None
+                }
+                Key::Char('\n') => {
+                    let signal = signals.items[signals.state.selected().unwrap_or(0)];
+                    *self = Dialog::ConfirmSignal {
+                        target: *target,
+                        name: name.clone(),
+                        signal,
+                    };
+                    DialogAction::None
+                }
+                _ => DialogAction::None,
+            },
+            Dialog::ConfirmSignal { target, signal, .. } => match key {
+                Key::Char('y') | Key::Char('Y') => DialogAction::SendSignal(*target, signal.1),
+                _ => DialogAction::Close,
+            },
+            Dialog::Affinity { target, cpus, .. } => match key {
+                Key::Esc | Key::Char('q') => DialogAction::Close,
+                Key::Down | Key::Char('j') => {
+                    cpus.next();
+                    DialogAction::None
+                }
+                Key::Up | Key::Char('k') => {
+                    cpus.previous();
+                    DialogAction::None
+                }
+                Key::Char(' ') => {
+                    if let Some(i) = cpus.state.selected() {
+                        cpus.items[i] = !cpus.items[i];
+                    }
+                    DialogAction::None
+                }
+                Key::Char('a') => {
+                    // all on, unless they already are - then all off
+                    let value = !cpus.items.iter().all(|allowed| *allowed);
+                    cpus.items.iter_mut().for_each(|allowed| *allowed = value);
+                    DialogAction::None
+                }
+                Key::Char('\n') => DialogAction::SetAffinity(*target, cpus.items.clone()),
+                _ => DialogAction::None,
+            },
+        }
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>) {
+        match self {
+            Dialog::SignalPicker {
+                target,
+                name,
+                signals,
+            } => {
+                let title = format!("Send signal to {} ({})", display_id(target), name);
+                let width = (title.len() as u16 + 4).max(30);
+                let area = centered_rect(width, signals.items.len() as u16 + 2, f.size());
+                let items: Vec<ListItem> = signals
+                    .items
+                    .iter()
+                    .map(|(signal_name, number)| {
+                        ListItem::new(format!("{:2} SIG{}", number, signal_name))
+                    })
+                    .collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(title))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                f.render_widget(Clear, area);
+                f.render_stateful_widget(list, area, &mut signals.state);
+            }
+            Dialog::ConfirmSignal {
+                target,
+                name,
+                signal,
+            } => {
+                let question = format!(
+                    "Send SIG{} to {} ({})? ",
+                    signal.0,
+                    display_id(target),
+                    name
+                );
+                let area = centered_rect(question.len() as u16 + 7, 3, f.size());
+                let text = Spans::from(vec![
+                    Span::raw(question),
+                    Span::styled("[y/N]", Style::default().fg(Color::Yellow)),
+                ]);
+                let paragraph = Paragraph::new(text)
+                    .block(Block::default().borders(Borders::ALL).title("Confirm"));
+                f.render_widget(Clear, area);
+                f.render_widget(paragraph, area);
+            }
+            Dialog::Affinity { target, name, cpus } => {
+                let title = format!("CPUs for {} ({})", display_id(target), name);
+                let width = (title.len() as u16 + 4).max(30);
+                let area = centered_rect(width, cpus.items.len() as u16 + 2, f.size());
+                let items: Vec<ListItem> = cpus
+                    .items
+                    .iter()
+                    .enumerate()
+                    .map(|(cpu, allowed)| {
+                        let check = if *allowed { "[x]" } else { "[ ]" };
+                        ListItem::new(format!("{} CPU {}", check, cpu))
+                    })
+                    .collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title(title))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                f.render_widget(Clear, area);
+                f.render_stateful_widget(list, area, &mut cpus.state);
+                // key help on the bottom border
+                if area.height > 2 && area.width > 2 {
+                    let help = Rect::new(area.x + 1, area.bottom() - 1, area.width - 2, 1);
+                    let text = Span::styled(
+                        "space: toggle  a: all  enter: apply",
+                        Style::default().fg(Color::Yellow),
+                    );
+                    f.render_widget(Paragraph::new(text), help);
+                }
+            }
+        }
+    }
+}
+
+fn display_id(id: &RowId) -> String {
+    match id.tid {
+        Some(tid) => format!("thread {}", tid),
+        None => id.pid.to_string(),
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::cpu_stat::CpuBreakdown;
+use crate::meminfo::MemoryBreakdown;
+use crate::psi::{Pressure, PressureLine, ResourcePressure};
+use crate::snapshot::{Snapshot, SystemRecord};
+use crate::zram::ZramDevice;
+use crate::zswap::{ZswapParameters, ZswapRates, ZswapStats};
+
+/// how batch mode prints each refresh
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    /// like top -b
+    Text,
+    /// one object per line
+    Json,
+    /// one row per process
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown format `{}` - try text, json or csv", s)),
+        }
+    }
+}
+
+pub fn write_json(out: &mut impl Write, snapshot: &Snapshot) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, snapshot)?;
+    writeln!(out)
+}
+
+/// one row per process per refresh. the time and system values are repeated on
+/// every row, so each row stands on its own in a spreadsheet. nested fields get
+/// dotted names, like system.vmstat.swap_in
+///
+/// the columns can't change after the header is written, so the system columns
+/// come from `system_schema`, which has every optional part filled in. parts
+/// that weren't read this refresh (zswap stats after a failed read, pressure on
+/// a kernel without it, ...) are left empty
+pub struct CsvWriter {
+    /// the columns, written with the first row and kept for the rest
+    header: Option<Vec<String>>,
+    /// the same columns, to look them up quickly
+    columns: HashSet<String>,
+}
+
+impl CsvWriter {
+    pub fn new() -> Self {
+        Self {
+            header: None,
+            columns: HashSet::new(),
+        }
+    }
+
+    pub fn write(&mut self, out: &mut impl Write, snapshot: &Snapshot) -> io::Result<()> {
+        let value = serde_json::to_value(snapshot)?;
+        let mut shared = vec![(String::from("time"), value_to_string(&value["time"]))];
+        flatten("system", &value["system"], &mut shared);
+        let processes = match &value["processes"] {
+            Value::Array(processes) => processes.as_slice(),
+            _ => &[],
+        };
+        for process in processes {
+            let mut row = shared.clone();
+            flatten("", process, &mut row);
+            if self.header.is_none() {
+                let schema = serde_json::to_value(system_schema())?;
+                let mut columns = vec![(String::from("time"), String::new())];
+                flatten("system", &schema, &mut columns);
+                flatten("", process, &mut columns);
+                let header: Vec<String> = columns.into_iter().map(|(name, _)| name).collect();
+                write_csv_line(out, &header)?;
+                self.columns = header.iter().cloned().collect();
+                self.header = Some(header);
+            }
+            // an empty list has no fields to name its columns by, but there's
+            // nothing in it to lose either
+            let unknown = row
+                .iter()
+                .find(|(name, value)| !value.is_empty() && !self.columns.contains(name));
+            if let Some((name, _)) = unknown {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} isn't one of the csv columns - use --format json instead",
+                        name
+                    ),
+                ));
+            }
+            let row: HashMap<String, String> = row.into_iter().collect();
+            if let Some(header) = &self.header {
+                let fields: Vec<String> = header
+                    .iter()
+                    .map(|name| row.get(name).cloned().unwrap_or_default())
+                    .collect();
+                write_csv_line(out, &fields)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for CsvWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a system record with every optional part filled in and one of everything
+/// in the lists, so the header has a column for anything a refresh might have
+fn system_schema() -> SystemRecord {
+    let pressure = ResourcePressure {
+        some: PressureLine::default(),
+        full: Some(PressureLine::default()),
+    };
+    SystemRecord {
+        cores: vec![CpuBreakdown::default()],
+        memory: Some(MemoryBreakdown::default()),
+        pressure: Some(Pressure {
+            cpu: pressure,
+            memory: pressure,
+            io: pressure,
+        }),
+        zswap: Some(ZswapStats::default()),
+        zswap_rates: Some(ZswapRates::default()),
+        zswap_parameters: Some(ZswapParameters::default()),
+        zram: vec![ZramDevice::default()],
+        ..SystemRecord::default()
+    }
+}
+
+/// objects become one column per field. lists of plain values, like a command
+/// line, are joined with spaces. lists of objects get a column per field, with
+/// every item's value joined with spaces the same way, so the columns don't
+/// depend on how many cores or zram devices there are
+fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    let name = |key: &str| match prefix {
+        "" => key.to_string(),
+        _ => format!("{}.{}", prefix, key),
+    };
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                flatten(&name(key), value, out);
+            }
+        }
+        Value::Array(items) if items.iter().any(|i| i.is_object() || i.is_array()) => {
+            let mut columns: Vec<(String, Vec<String>)> = Vec::new();
+            for item in items {
+                let mut fields = Vec::new();
+                flatten(prefix, item, &mut fields);
+                for (name, value) in fields {
+                    match columns.iter_mut().find(|(column, _)| *column == name) {
+                        Some((_, values)) => values.push(value),
+                        None => columns.push((name, vec![value])),
+                    }
+                }
+            }
+            out.extend(
+                columns
+                    .into_iter()
+                    .map(|(name, values)| (name, values.join(" "))),
+            );
+        }
+        Value::Array(items) => {
+            let joined: Vec<String> = items.iter().map(value_to_string).collect();
+            out.push((prefix.to_string(), joined.join(" ")));
+        }
+        _ => out.push((prefix.to_string(), value_to_string(value))),
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn write_csv_line(out: &mut impl Write, fields: &[String]) -> io::Result<()> {
+    let fields: Vec<String> = fields.iter().map(|f| escape_csv(f)).collect();
+    writeln!(out, "{}", fields.join(","))
+}
+
+/// quotes a field if it has to be, doubling any quotes inside it
+fn escape_csv(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::fixture::FixtureSource;
+    use crate::source::{MetricsSource, SnapshotOptions};
+
+    fn fixture_snapshot() -> Snapshot {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src", "fixtures", "small"]
+            .iter()
+            .collect();
+        let options = SnapshotOptions {
+            show_threads: false,
+            read_proc_stat: true,
+            read_affinity: false,
+        };
+        FixtureSource::open(&dir)
+            .unwrap()
+            .refresh(&options)
+            .unwrap()
+    }
+
+    #[test]
+    fn csv_keeps_its_columns() {
+        let mut with_zswap = fixture_snapshot();
+        with_zswap.system.zswap = Some(ZswapStats {
+            stored_pages: 1234,
+            ..ZswapStats::default()
+        });
+        // the zswap read failed, and a zram device was set up since
+        let mut without_zswap = fixture_snapshot();
+        without_zswap.system.zswap_error = Some(String::from("permission denied"));
+        without_zswap.system.zram = vec![
+            ZramDevice {
+                name: String::from("zram0"),
+                ..ZramDevice::default()
+            },
+            ZramDevice {
+                name: String::from("zram1"),
+                ..ZramDevice::default()
+            },
+        ];
+        let mut csv = CsvWriter::new();
+        let mut out = Vec::new();
+        csv.write(&mut out, &with_zswap).unwrap();
+        csv.write(&mut out, &without_zswap).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<Vec<&str>> = out.lines().map(|l| l.split(',').collect()).collect();
+        let processes = with_zswap.processes.len();
+        assert_eq!(lines.len(), 1 + 2 * processes);
+        let column = |name: &str| lines[0].iter().position(|c| *c == name).unwrap();
+        let (stored, error, zram) = (
+            column("system.zswap.stored_pages"),
+            column("system.zswap_error"),
+            column("system.zram.name"),
+        );
+        for line in &lines[1..=processes] {
+            assert_eq!(line.len(), lines[0].len());
+            assert_eq!((line[stored], line[error], line[zram]), ("1234", "", ""));
+        }
+        for line in &lines[1 + processes..] {
+            assert_eq!(line.len(), lines[0].len());
+            assert_eq!(
+                (line[stored], line[error], line[zram]),
+                ("", "permission denied", "zram0 zram1")
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::snapshot::ProcessRecord;
+use regex::{Regex, RegexBuilder};
+
+/// plain text matches case insensitively anywhere in the string. text wrapped
+/// in slashes, like /^kworker/, is a regex
+enum TextMatcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl TextMatcher {
+    fn parse(s: &str) -> Result<Self, String> {
+        if s.len() >= 2 && s.starts_with('/') && s.ends_with('/') {
+            let pattern = &s[1..s.len() - 1];
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map(TextMatcher::Regex)
+                .map_err(|_| format!("invalid regex {}", s))
+        } else {
+            Ok(TextMatcher::Substring(s.to_lowercase()))
+        }
+    }
+
+    fn is_match(&self, haystack: &str) -> bool {
+        match self {
+            TextMatcher::Substring(needle) => haystack.to_lowercase().contains(needle),
+            TextMatcher::Regex(re) => re.is_match(haystack),
+        }
+    }
+}
+
+/// "1000", ">1000", "<=5" and so on
+struct NumberMatcher {
+    comparison: &'static str,
+    value: f64,
+}
+
+impl NumberMatcher {
+    fn parse(s: &str) -> Result<Self, String> {
+        // longest operators first, so ">=" isn't read as ">"
+        let comparison = [">=", "<=", ">", "<", "="]
+            .iter()
+            .find(|op| s.starts_with(*op))
+            .copied()
+            .unwrap_or("=");
+        let number = s.trim_start_matches(comparison);
+        match number.parse() {
+            Ok(value) => Ok(NumberMatcher { comparison, value }),
+            Err(_) => Err(format!("`{}` is not a number", number)),
+        }
+    }
+
+    fn is_match(&self, n: f64) -> bool {
+        match self.comparison {
+            ">=" => n >= self.value,
+            "<=" => n <= self.value,
+            ">" => n > self.value,
+            "<" => n < self.value,
+            _ => (n - self.value).abs() < f64::EPSILON,
+        }
+    }
+}
+
+enum Term {
+    /// no key: checks the name and the command line
+    Text(TextMatcher),
+    Name(TextMatcher),
+    Cmd(TextMatcher),
+    User(TextMatcher),
+    Pid(NumberMatcher),
+    Ppid(NumberMatcher),
+    Cpu(NumberMatcher),
+}
+
+/// a query typed into the search or filter box. space separated terms, all of
+/// which have to match, e.g. `user:root pid:>1000 /^kworker/`
+pub struct ProcessFilter {
+    terms: Vec<Term>,
+}
+
+impl ProcessFilter {
+    /// Ok(None) for an empty query
+    pub fn parse(query: &str) -> Result<Option<Self>, String> {
+        let terms = split_terms(query)
+            .into_iter()
+            .map(parse_term)
+            .collect::<Result<Vec<_>, _>>()?;
+        if terms.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(ProcessFilter { terms }))
+    }
+
+    pub fn matches(&self, process: &ProcessRecord) -> bool {
+        self.terms.iter().all(|term| match term {
+            Term::Text(m) => m.is_match(&process.name) || m.is_match(&process.cmd.join(" ")),
+            Term::Name(m) => m.is_match(&process.name),
+            Term::Cmd(m) => m.is_match(&process.cmd.join(" ")),
+            Term::User(m) => m.is_match(&process.user),
+            Term::Pid(m) => m.is_match(process.task_pid() as f64),
+            Term::Ppid(m) => matches!(process.ppid, Some(p) if m.is_match(p as f64)),
+            Term::Cpu(m) => m.is_match(process.cpu_percent as f64),
+        })
+    }
+}
+
+/// splits a query on whitespace, except inside a regex, so `/foo bar/` and
+/// `cmd:/-c .*/` are one term each
+fn split_terms(query: &str) -> Vec<&str> {
+    let mut terms = vec![];
+    let mut start = None;
+    let mut in_regex = false;
+    let mut escaped = false;
+    for (i, c) in query.char_indices() {
+        let term_start = match start {
+            Some(term_start) => term_start,
+            None if c.is_whitespace() => continue,
+            None => {
+                start = Some(i);
+                i
+            }
+        };
+        if in_regex {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '/' => in_regex = false,
+                _ => {}
+            }
+        } else if c == '/' {
+            // only a slash at the start of the term, or right after the key, opens a regex
+            let before = &query[term_start..i];
+            in_regex = before.is_empty() || before.ends_with(':');
+        } else if c.is_whitespace() {
+            terms.push(&query[term_start..i]);
+            start = None;
+        }
+    }
+    if let Some(term_start) = start {
+        terms.push(&query[term_start..]);
+    }
+    terms
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    let (key, value) = match term.find(':') {
+        Some(i) => (&term[..i], &term[i + 1..]),
+        None => return TextMatcher::parse(term).map(Term::Text),
+    };
+    match key {
+        "name" => TextMatcher::parse(value).map(Term::Name),
+        "cmd" => TextMatcher::parse(value).map(Term::Cmd),
+        "user" => TextMatcher::parse(value).map(Term::User),
+        "pid" => NumberMatcher::parse(value).map(Term::Pid),
+        "ppid" => NumberMatcher::parse(value).map(Term::Ppid),
+        "cpu" => NumberMatcher::parse(value).map(Term::Cpu),
+        // not a key we know, so just search for it - it might be a url or something
+        _ => TextMatcher::parse(term).map(Term::Text),
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum QueryKind {
+    /// highlight and jump to matching rows
+    Search,
+    /// hide rows that don't match
+    Filter,
+}
+
+/// the inline text box opened with / or \
+pub struct QueryInput {
+    pub kind: QueryKind,
+    pub text: String,
+}
+
+impl QueryInput {
+    pub fn prompt(&self) -> &str {
+        match self.kind {
+            QueryKind::Search => "/",
+            QueryKind::Filter => "\\",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regexes_keep_their_spaces() {
+        assert_eq!(
+            split_terms("  user:root /foo bar/ cmd:/-c  .*/ pid:>1000 "),
+            vec!["user:root", "/foo bar/", "cmd:/-c  .*/", "pid:>1000"]
+        );
+        assert_eq!(split_terms(r"/a\/ b/ c"), vec![r"/a\/ b/", "c"]);
+        // not closed yet, e.g. while it's being typed
+        assert_eq!(split_terms("/foo bar"), vec!["/foo bar"]);
+        assert_eq!(split_terms("a/b c"), vec!["a/b", "c"]);
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cpu_stat::CpuBreakdown;
+use crate::meminfo;
+use crate::proc_stat::{self, read_proc_stat, read_status_field, tty_name};
+use crate::processes::user_name;
+use crate::snapshot::{ProcessRecord, Snapshot, SystemRecord};
+use crate::source::{self, KernelStats, MetricsSource, SnapshotOptions};
+use crate::zswap::ZswapSource;
+
+/// cpu ticks (user + system) of every task, by pid and tid
+type Ticks = HashMap<(i32, Option<i32>), u64>;
+
+/// `--fixture`: /proc, and optionally /sys and /etc/passwd, copied off some
+/// other machine. a directory with a proc directory in it is one capture. a
+/// directory of captures (0/, 1/, 2/, ...) moves on to the next one every
+/// refresh, so the rates and cpu usage have something to compare against. the
+/// last one is shown for good once it's reached
+pub struct FixtureSource {
+    captures: Vec<PathBuf>,
+    /// the capture the next refresh reads
+    position: usize,
+    stats: KernelStats,
+    /// cpu ticks of every task in the capture before the one being read, for
+    /// the cpu column. the final capture keeps being compared with the one
+    /// before it, not with itself
+    last_ticks: Ticks,
+    /// seconds since boot when that capture was taken
+    last_uptime: f64,
+    /// the system part of the final capture once it's been read, since reading
+    /// it again would work out every rate over no time at all
+    final_system: Option<SystemRecord>,
+}
+
+impl FixtureSource {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let captures = match dir.join("proc").is_dir() {
+            true => vec![dir.to_path_buf()],
+            false => {
+                let mut captures: Vec<PathBuf> = fs::read_dir(dir)?
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.join("proc").is_dir())
+                    .collect();
+                // 2/ before 10/
+                captures.sort_by_key(|path| {
+                    let name = path.file_name().map(|n| n.to_string_lossy().to_string());
+                    let name = name.unwrap_or_default();
+                    (name.parse::<u64>().ok(), name)
+                });
+                captures
+            }
+        };
+        if captures.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no proc directory in it, or in any directory under it",
+            ));
+        }
+        Ok(Self {
+            captures,
+            position: 0,
+            stats: KernelStats::new(),
+            last_ticks: HashMap::new(),
+            last_uptime: 0.0,
+            final_system: None,
+        })
+    }
+
+    /// every task under /proc, like sysinfo would list them, and the cpu ticks
+    /// of each one
+    fn read_processes(
+        &self,
+        root: &Path,
+        options: &SnapshotOptions,
+        uptime: f64,
+    ) -> (Vec<ProcessRecord>, Ticks) {
+        let user_names = proc_stat::read_user_names(root);
+        let boot_time = read_boot_time(root);
+        let mut pids: Vec<i32> = fs::read_dir(root.join("proc"))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| entry.file_name().to_string_lossy().parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        pids.sort_unstable();
+        let mut tasks = Vec::new();
+        for pid in pids {
+            let dir = proc_stat::process_dir(root, pid);
+            tasks.push((pid, None, dir.clone()));
+            if options.show_threads {
+                let mut tids: Vec<i32> = fs::read_dir(dir.join("task"))
+                    .map(|entries| {
+                        entries
+                            .filter_map(|entry| entry.ok())
+                            .filter_map(|entry| entry.file_name().to_string_lossy().parse().ok())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                tids.sort_unstable();
+                for tid in tids {
+                    tasks.push((pid, Some(tid), dir.join("task").join(tid.to_string())));
+                }
+            }
+        }
+        let clock_ticks = clock_ticks();
+        let elapsed = uptime - self.last_uptime;
+        let mut ticks = HashMap::new();
+        let records = tasks
+            .into_iter()
+            .filter_map(|(pid, tid, dir)| {
+                // the task might not have been copied whole
+                let stat = read_proc_stat(&dir)?;
+                let task_ticks = stat.utime + stat.stime;
+                let cpu_percent = match (self.last_ticks.get(&(pid, tid)), elapsed > 0.0) {
+                    (Some(last), true) => {
+                        task_ticks.saturating_sub(*last) as f64 / clock_ticks / elapsed * 100.0
+                    }
+                    _ => 0.0,
+                };
+                ticks.insert((pid, tid), task_ticks);
+                let kb = |name: &str| {
+                    read_status_field(&dir, name)
+                        .and_then(|v| v.trim_end_matches("kB").trim().parse().ok())
+                        .unwrap_or(0)
+                };
+                let uid = read_status_field(&dir, "Uid:")
+                    .and_then(|v| v.split_whitespace().next().and_then(|u| u.parse().ok()))
+                    .unwrap_or(0);
+                let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap_or_default();
+                Some(ProcessRecord {
+                    pid,
+                    tid,
+                    ppid: match tid {
+                        Some(_) => Some(pid),
+                        None => Some(stat.ppid).filter(|p| *p != 0),
+                    },
+                    name: read("comm").trim_end().to_string(),
+                    cmd: read("cmdline")
+                        .split('\0')
+                        .filter(|arg| !arg.is_empty())
+                        .map(String::from)
+                        .collect(),
+                    exe: fs::read_link(dir.join("exe"))
+                        .map(|exe| exe.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    uid,
+                    user: user_name(&user_names, uid),
+                    cpu_percent: cpu_percent as f32,
+                    memory: kb("VmRSS:"),
+                    virtual_memory: kb("VmSize:"),
+                    shared_memory: stat.shared,
+                    swap: kb("VmSwap:"),
+                    runtime: (task_ticks as f64 / clock_ticks) as u64,
+                    start_time: boot_time + (stat.start_time as f64 / clock_ticks) as u64,
+                    state: stat.state,
+                    priority: stat.priority,
+                    nice: stat.nice,
+                    threads: stat.num_threads,
+                    tty: tty_name(stat.tty_nr),
+                    cpus_allowed: proc_stat::read_cpus_allowed(&dir)
+                        .unwrap_or_else(|| String::from("?")),
+                })
+            })
+            .collect();
+        (records, ticks)
+    }
+}
+
+impl MetricsSource for FixtureSource {
+    fn refresh(&mut self, options: &SnapshotOptions) -> io::Result<Snapshot> {
+        let root = self.captures[self.position].clone();
+        let is_final = self.position + 1 == self.captures.len();
+        if !is_final {
+            self.position += 1;
+        }
+        let uptime = source::read_uptime(&root).unwrap_or(0.0);
+        let (processes, ticks) = self.read_processes(&root, options, uptime);
+        if !is_final {
+            self.last_ticks = ticks;
+            self.last_uptime = uptime;
+        }
+        if let Some(system) = &self.final_system {
+            return Ok(Snapshot {
+                time: source::now(),
+                system: system.clone(),
+                processes,
+            });
+        }
+        let debugfs = root.join("sys/kernel/debug");
+        let mut zswap_source = match debugfs.join("zswap").is_dir() {
+            true => Some(ZswapSource::Debugfs(debugfs)),
+            false => None,
+        };
+        self.stats.update(&root, zswap_source.as_mut());
+        let system = self.stats.system_record(&root, zswap_source.as_ref());
+        let read = |file: &str| fs::read_to_string(root.join(file)).unwrap_or_default();
+        let numbers = |contents: String| -> Vec<f64> {
+            contents
+                .split_whitespace()
+                .map_while(|n| n.parse().ok())
+                .collect()
+        };
+        let load = numbers(read("proc/loadavg"));
+        let load = |i: usize| load.get(i).copied().unwrap_or(0.0);
+        // what sysinfo counts as busy: everything but idle and iowait
+        let busy =
+            |cpu: &CpuBreakdown| (cpu.user + cpu.nice + cpu.system + cpu.irq + cpu.steal) * 100.0;
+        let memory = meminfo::read_meminfo(&root).unwrap_or_default();
+        let used = memory.total.saturating_sub(
+            memory
+                .available
+                .unwrap_or(memory.free + memory.buffers + memory.cached),
+        );
+        let system = SystemRecord {
+            uptime: uptime as u64,
+            load_average: [load(0), load(1), load(2)],
+            cpu_percent: busy(&system.cpu),
+            cpu_system_percent: system.cpu.system * 100.0,
+            per_cpu_percent: system.cores.iter().map(busy).collect(),
+            memory_used: used,
+            memory_total: memory.total,
+            swap_used: memory.swap_total.saturating_sub(memory.swap_free),
+            swap_total: memory.swap_total,
+            ..system
+        };
+        if is_final {
+            self.final_system = Some(system.clone());
+        }
+        Ok(Snapshot {
+            time: source::now(),
+            system,
+            processes,
+        })
+    }
+}
+
+/// when the machine booted, in seconds since the unix epoch
+fn read_boot_time(root: &Path) -> u64 {
+    fs::read_to_string(root.join("proc/stat"))
+        .ok()
+        .and_then(|stat| {
+            stat.lines()
+                .find(|line| line.starts_with("btime "))
+                .and_then(|line| line["btime ".len()..].trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+/// the units of the cpu times in /proc/[pid]/stat. 100 everywhere that matters
+fn clock_ticks() -> f64 {
+    unsafe { libc::sysconf(libc::_SC_CLK_TCK) as f64 }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// two captures of a tiny machine, two seconds apart
+    fn small_fixture() -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "src", "fixtures", "small"]
+            .iter()
+            .collect()
+    }
+
+    const OPTIONS: SnapshotOptions = SnapshotOptions {
+        show_threads: true,
+        read_proc_stat: true,
+        read_affinity: true,
+    };
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn refresh() {
+        let mut fixture = FixtureSource::open(&small_fixture()).unwrap();
+        let first = fixture.refresh(&OPTIONS).unwrap();
+        // nothing to compare against yet
+        assert!(first.processes.iter().all(|p| p.cpu_percent == 0.0));
+        let snapshot = fixture.refresh(&OPTIONS).unwrap();
+
+        let system = &snapshot.system;
+        assert_eq!(system.uptime, 1002);
+        assert_eq!(system.load_average, [0.52, 0.41, 0.30]);
+        assert!(close(system.cpu_percent, 37.5));
+        assert!(close(system.cpu_system_percent, 7.5));
+        assert_eq!(system.per_cpu_percent.len(), 2);
+        assert!(close(system.per_cpu_percent[0], 60.0));
+        assert!(close(system.per_cpu_percent[1], 15.0));
+        assert!(close(system.cores[0].iowait, 0.1));
+        assert_eq!(system.memory_total, 8_000_000);
+        assert_eq!(system.memory_used, 3_000_000);
+        assert_eq!(system.swap_total, 2_000_000);
+        assert_eq!(system.swap_used, 500_000);
+        let memory = system.memory.unwrap();
+        assert!(close(memory.buffers, 0.0125));
+        assert!(close(memory.shared, 0.025));
+        assert_eq!(system.vmstat.swap_in, 100.0);
+        assert_eq!(system.vmstat.swap_out, 300.0);
+        assert_eq!(system.vmstat.page_in, 200.0);
+        assert_eq!(system.vmstat.faults, 1000.0);
+        assert_eq!(system.vmstat.oom_kills, 0);
+        assert_eq!(system.vmstat.oom_kills_total, 1);
+
+        let ids: Vec<(i32, Option<i32>)> =
+            snapshot.processes.iter().map(|p| (p.pid, p.tid)).collect();
+        assert_eq!(
+            ids,
+            vec![
+                (1, None),
+                (420, None),
+                (421, None),
+                (421, Some(421)),
+                (421, Some(425))
+            ]
+        );
+        let cpu: Vec<f32> = snapshot.processes.iter().map(|p| p.cpu_percent).collect();
+        for (cpu, expected) in cpu.iter().zip(&[0.0, 1.0, 75.0, 55.0, 20.0]) {
+            assert!(close(*cpu, *expected), "{} != {}", cpu, expected);
+        }
+        let cargo = &snapshot.processes[2];
+        assert_eq!(cargo.name, "cargo");
+        assert_eq!(cargo.cmd, vec!["cargo", "test", "--release"]);
+        assert_eq!(cargo.user, "kurt");
+        assert_eq!(cargo.ppid, Some(420));
+        assert_eq!(cargo.state, 'R');
+        assert_eq!(cargo.threads, 2);
+        assert_eq!(cargo.memory, 250_000);
+        assert_eq!(cargo.runtime, 36);
+        assert_eq!(cargo.tty, "pts/0");
+        assert_eq!(cargo.cpus_allowed, "0-1");
+        assert_eq!(cargo.start_time, 1_700_000_000 + 900);
+        let thread = &snapshot.processes[4];
+        assert_eq!(thread.name, "rustc");
+        assert_eq!(thread.ppid, Some(421));
+        assert_eq!(snapshot.processes[0].ppid, None);
+        assert_eq!(snapshot.processes[0].user, "root");
+
+        // the last capture stays up, rates and all
+        for _ in 0..2 {
+            let again = fixture.refresh(&OPTIONS).unwrap();
+            assert_eq!(again.system.uptime, 1002);
+            assert_eq!(again.system.vmstat.swap_out, 300.0);
+            assert!(close(again.system.cpu_percent, 37.5));
+            assert!(close(again.system.per_cpu_percent[0], 60.0));
+            let cpu: Vec<f32> = again.processes.iter().map(|p| p.cpu_percent).collect();
+            for (cpu, expected) in cpu.iter().zip(&[0.0, 1.0, 75.0, 55.0, 20.0]) {
+                assert!(close(*cpu, *expected), "{} != {}", cpu, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn hide_threads() {
+        let mut fixture = FixtureSource::open(&small_fixture()).unwrap();
+        let options = SnapshotOptions {
+            show_threads: false,
+            ..OPTIONS
+        };
+        let snapshot = fixture.refresh(&options).unwrap();
+        assert!(snapshot.processes.iter().all(|p| p.tid.is_none()));
+        assert_eq!(snapshot.processes.len(), 3);
+    }
+
+    #[test]
+    fn not_a_capture() {
+        let dir = small_fixture().join("0").join("proc");
+        assert!(FixtureSource::open(&dir).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+/// the last `capacity` samples of one metric, oldest first
+pub struct History<T> {
+    samples: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T: Copy> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// adds a sample, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, sample: T) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn latest(&self) -> Option<T> {
+        self.samples.back().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.samples.iter()
+    }
+}
+
+/// everything the graph panel draws. one sample per tick
+pub struct Histories {
+    /// percentages, 0 to 100
+    pub cpu: History<f64>,
+    pub memory: History<f64>,
+    pub swap: History<f64>,
+    /// pages swapped in/out per second
+    pub swap_in: History<u64>,
+    pub swap_out: History<u64>,
+    /// seconds between samples
+    pub interval: u64,
+}
+
+impl Histories {
+    /// enough room for `window` seconds of samples taken every `interval` seconds
+    pub fn new(window: u64, interval: u64) -> Self {
+        let interval = interval.max(1);
+        let capacity = (window / interval).max(2) as usize;
+        Self {
+            cpu: History::new(capacity),
+            memory: History::new(capacity),
+            swap: History::new(capacity),
+            swap_in: History::new(capacity),
+            swap_out: History::new(capacity),
+            interval,
+        }
+    }
+
+    /// how many seconds the full buffer covers
+    pub fn window(&self) -> u64 {
+        self.cpu.capacity() as u64 * self.interval
+    }
+}
@@ -0,0 +1,116 @@
+use tui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    symbols,
+    text::Span,
+    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Sparkline, Widget},
+};
+
+use crate::history::{Histories, History};
+use crate::util::format_seconds;
+
+/// cpu/memory/swap usage over time on the left, swap activity on the right
+pub struct HistoryWidget<'a> {
+    pub histories: &'a Histories,
+}
+
+impl<'a> Widget for HistoryWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+            .split(area);
+        self.render_usage(columns[0], buf);
+
+        let rows = Layout::default()
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(columns[1]);
+        render_sparkline(
+            &self.histories.swap_in,
+            "Swap in",
+            Color::Green,
+            rows[0],
+            buf,
+        );
+        render_sparkline(
+            &self.histories.swap_out,
+            "Swap out",
+            Color::Red,
+            rows[1],
+            buf,
+        );
+    }
+}
+
+impl<'a> HistoryWidget<'a> {
+    fn render_usage(&self, area: Rect, buf: &mut Buffer) {
+        let interval = self.histories.interval as f64;
+        // newest sample at x = 0, older ones at negative seconds
+        let points = |history: &History<f64>| -> Vec<(f64, f64)> {
+            let len = history.len();
+            history
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (-((len - 1 - i) as f64) * interval, *v))
+                .collect()
+        };
+        let cpu = points(&self.histories.cpu);
+        let memory = points(&self.histories.memory);
+        let swap = points(&self.histories.swap);
+        let dataset = |name: &'static str, color: Color, data| {
+            Dataset::default()
+                .name(name)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(data)
+        };
+        let datasets = vec![
+            dataset("CPU", Color::Green, &cpu),
+            dataset("MEM", Color::Yellow, &memory),
+            dataset("SWAP", Color::Red, &swap),
+        ];
+
+        let window = self.histories.window();
+        let chart = Chart::new(datasets)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Last {}", format_seconds(window))),
+            )
+            .x_axis(Axis::default().bounds([-(window as f64), 0.0]).labels(vec![
+                Span::raw(format!("-{}", format_seconds(window))),
+                Span::raw(format!("-{}", format_seconds(window / 2))),
+                Span::raw("now"),
+            ]))
+            .y_axis(Axis::default().bounds([0.0, 100.0]).labels(vec![
+                Span::raw("0%"),
+                Span::raw("50%"),
+                Span::raw("100%"),
+            ]));
+        chart.render(area, buf);
+    }
+}
+
+/// a bordered sparkline of the newest samples that fit, titled with the latest value
+fn render_sparkline(
+    history: &History<u64>,
+    name: &str,
+    color: Color,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let width = area.width.saturating_sub(2) as usize;
+    let data: Vec<u64> = history
+        .iter()
+        .skip(history.len().saturating_sub(width))
+        .copied()
+        .collect();
+    let title = format!("{}: {} pages/s", name, history.latest().unwrap_or(0));
+    Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .style(Style::default().fg(color))
+        .data(&data)
+        .render(area, buf);
+}
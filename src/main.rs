@@ -1,61 +1,159 @@
 use clap::{App, Arg};
+mod batch;
+mod columns;
 mod config;
-use sysinfo::{ProcessorExt, System, SystemExt};
-use vmstat::vmstat_info;
+mod cpu_stat;
+mod dialog;
+mod export;
+mod filter;
+mod fixture;
+mod proc_stat;
+mod process_actions;
 mod processes;
+mod psi;
+mod record;
+mod remote;
+#[cfg(test)]
+mod render_tests;
+mod replay;
+mod snapshot;
+mod source;
 mod util;
 
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::{error::Error, io};
 use std::{time::Duration, vec};
 use termion::{event::Key, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{
-    backend::TermionBackend,
-    layout::{Constraint, Layout},
+    backend::{Backend, TermionBackend},
+    layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Cell, Row, Table},
-    Terminal,
+    text::{Span, Spans},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    Frame, Terminal,
 };
 use util::event::{Config, Event, Events};
 use util::StatefulTable;
 
+use crate::columns::{Column, ColumnType};
+use crate::config::AppConfig;
 use crate::debug_permissions::DebugfsStatus;
+use crate::dialog::{Dialog, DialogAction};
+use crate::export::OutputFormat;
+use crate::filter::{ProcessFilter, QueryInput, QueryKind};
+use crate::fixture::FixtureSource;
+use crate::history::Histories;
+use crate::history_widget::HistoryWidget;
 use crate::meter_widget::MeterWidget;
-use crate::zswap::read_zswap_stats;
+use crate::processes::RowId;
+use crate::record::Recorder;
+use crate::remote::RemoteSource;
+use crate::replay::{Replay, SEEK_STEP};
+use crate::snapshot::Snapshot;
+use crate::source::{LiveSource, MetricsSource, SnapshotOptions};
+use crate::zswap::ZswapSource;
+use crate::zswap_helper::ZswapHelper;
+use crate::zswap_widget::{ZswapWidget, ZSWAP_PANEL_LINES};
 mod debug_permissions;
+mod history;
+mod history_widget;
+mod meminfo;
 mod meter_widget;
 mod vmstat;
+mod zram;
 mod zswap;
+mod zswap_helper;
+mod zswap_widget;
+
+/// how often a replay checks whether it's time for the next frame
+const REPLAY_TICK: Duration = Duration::from_millis(200);
 
 pub struct AppState {
     sorting_by: Option<ColumnType>,
     sorting_column_index: usize,
-    can_use_debugfs: bool,
-    headers: Vec<ColumnType>,
-    vminfo: vmstat_info,
+    /// given with --debugfs-path, or found in /proc/self/mountinfo
+    debugfs_path: PathBuf,
+    /// this machine, or a captured /proc with --fixture
+    source: Box<dyn MetricsSource>,
+    headers: Vec<Column>,
+    history: Histories,
+    /// whether the graph panel is open
+    show_graphs: bool,
+    /// what the meters and the process table are drawn from. taken on every
+    /// refresh, or read from the recording when replaying
+    snapshot: Snapshot,
+    /// fill in every process field, not just the ones the table needs. set
+    /// when the snapshots are exported or recorded
+    read_all_fields: bool,
+    /// --record
+    recorder: Option<Recorder>,
+    /// --replay. nothing is read from this machine while it's set
+    replay: Option<Replay>,
+    /// whether the zswap panel is open
+    show_zswap: bool,
+    /// split the per cpu bars into user/system/iowait/...
+    cpu_breakdown: bool,
     show_threads: bool,
+    tree_view: bool,
+    /// pids whose children are hidden in tree view
+    collapsed: HashSet<i32>,
+    /// which process each row of the table is showing
+    row_ids: Vec<RowId>,
+    /// popup that gets keyboard input until it's closed
+    dialog: Option<Dialog>,
+    /// one line shown at the bottom of the process table, e.g. when a kill fails
+    status_message: Option<String>,
+    /// the status message is a failed refresh, to be cleared once one works again
+    refresh_failed: bool,
+    /// rows matching this get highlighted, and n/N jump between them
+    search: Option<ProcessFilter>,
+    /// rows not matching this are hidden
+    filter: Option<ProcessFilter>,
+    /// what was typed for `filter`, for the table title
+    filter_text: String,
+    /// the search/filter box, while it's being typed in
+    query_input: Option<QueryInput>,
+    /// which rows of the table match `search`
+    search_hits: Vec<bool>,
 }
-#[derive(PartialEq, Clone, Copy)]
-enum ColumnType {
-    PID,
-    NAME,
-    CPU,
-    RUNTIME,
-    MEMORY,
-    MEMORY_SWAP,
-}
-
-impl ColumnType {
-    fn value(&self) -> &str {
-        match *self {
-            ColumnType::PID => "PID",
-            ColumnType::NAME => "NAME",
-            ColumnType::RUNTIME => "TIME",
-            ColumnType::CPU => "CPU%",
-            ColumnType::MEMORY => "MEMORY",
-            ColumnType::MEMORY_SWAP => "SWAP",
+impl AppState {
+    /// the UI as the config says it should start out. recording, replaying and
+    /// the zswap panel are set up by main
+    fn new(app_config: &AppConfig, source: Box<dyn MetricsSource>) -> Self {
+        AppState {
+            show_zswap: false,
+            source,
+            debugfs_path: PathBuf::from(debug_permissions::DEFAULT_DEBUG_DIR),
+            sorting_column_index: app_config
+                .sort_by
+                .and_then(|key| app_config.columns.iter().position(|c| c.kind == key))
+                .unwrap_or(0),
+            headers: app_config.columns.clone(),
+            sorting_by: app_config.sort_by,
+            history: Histories::new(app_config.history_window, app_config.delay),
+            show_graphs: app_config.show_graphs,
+            snapshot: Snapshot::default(),
+            read_all_fields: app_config.format != OutputFormat::Text,
+            recorder: None,
+            replay: None,
+            cpu_breakdown: app_config.cpu_breakdown,
+            show_threads: app_config.show_threads,
+            tree_view: false,
+            collapsed: HashSet::new(),
+            row_ids: vec![],
+            dialog: None,
+            status_message: None,
+            refresh_failed: false,
+            search: None,
+            filter: None,
+            filter_text: String::new(),
+            query_input: None,
+            search_hits: vec![],
         }
     }
 }
+
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("ktop")
         .version("0.1.0")
@@ -65,14 +163,52 @@ fn main() -> Result<(), Box<dyn Error>> {
             Arg::with_name("zswap")
                 .short("z")
                 .long("zswap")
-                .help("read and display zswap debug stats"),
+                .help("read and display zswap debug stats. if debugfs is only readable by root, they're read by `ktop --zswap-helper` run with sudo"),
+        )
+        .arg(
+            Arg::with_name("zswap helper")
+                .long("zswap-helper")
+                .takes_value(false)
+                .hidden(true)
+                .help("run as the privileged zswap stats reader. started by ktop itself"),
+        )
+        .arg(
+            Arg::with_name("debugfs path")
+                .long("debugfs-path")
+                .takes_value(true)
+                .help("where debugfs is mounted. found from /proc/self/mountinfo by default"),
         )
         .arg(
             Arg::with_name("config file")
                 .short("c")
                 .long("config")
                 .takes_value(true)
-                .help("config file for ktop. not used yet"),
+                .help("config file for ktop. defaults to $XDG_CONFIG_HOME/ktop/config.toml"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .takes_value(true)
+                .help("process table columns in order, e.g. pid,cpu,mem,name. append :N to set a width, like name:40"),
+        )
+        .arg(
+            Arg::with_name("cpu breakdown")
+                .long("cpu-breakdown")
+                .takes_value(false)
+                .help("split per-cpu bars into user/nice/system/iowait/irq/steal"),
+        )
+        .arg(
+            Arg::with_name("graphs")
+                .short("g")
+                .long("graphs")
+                .takes_value(false)
+                .help("start with the history graphs open. toggle them with g"),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .takes_value(true)
+                .help("how many seconds of history the graphs show. defaults to 300"),
         )
         .arg(
             Arg::with_name("refresh time")
@@ -86,7 +222,81 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .short("o")
                 .long("once")
                 .takes_value(false)
-                .help("run once and exit"),
+                .help("print one snapshot and exit. same as --batch --iterations 1"),
+        )
+        .arg(
+            Arg::with_name("batch")
+                .short("b")
+                .long("batch")
+                .takes_value(false)
+                .help("print plain text snapshots to stdout instead of starting the UI, like top -b"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv"])
+                .help("batch mode output. json is one object per refresh, csv one row per process. json and csv imply --batch"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("save every refresh to FILE, to be watched later with --replay. works with --batch too"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&["record", "batch", "run once", "format", "zswap", "fixture"])
+                .help("play back a recording made with --record. space pauses, , and . step, [ and ] seek a minute, < and > change the speed"),
+        )
+        .arg(
+            Arg::with_name("fixture")
+                .long("fixture")
+                .takes_value(true)
+                .value_name("DIR")
+                .conflicts_with("zswap")
+                .help("show a /proc captured from another machine, e.g. DIR/proc, DIR/sys and DIR/etc/passwd. a directory of captures (DIR/0, DIR/1, ...) is shown one capture per refresh"),
+        )
+        .arg(
+            Arg::with_name("serve")
+                .long("serve")
+                .takes_value(true)
+                .value_name("ADDR")
+                .conflicts_with_all(&["connect", "replay", "fixture", "batch", "run once", "format", "record"])
+                .help("run without the UI, serving this machine's stats to `ktop --connect` on ADDR, e.g. 127.0.0.1:7171 for ssh tunnels, or an address on a network you trust. without --token, anyone who can reach ADDR can see the process list"),
+        )
+        .arg(
+            Arg::with_name("allow actions")
+                .long("allow-actions")
+                .takes_value(false)
+                .requires_all(&["serve", "token"])
+                .help("let clients with the token kill, renice and set the affinity of processes in their last refresh, as the user the agent runs as"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("a file holding a shared secret. `--serve` only answers clients that send the same one, and `--connect` sends it. it isn't encrypted on the wire, so tunnel over ssh across networks you don't trust"),
+        )
+        .arg(
+            Arg::with_name("connect")
+                .long("connect")
+                .takes_value(true)
+                .value_name("HOST:PORT")
+                .conflicts_with_all(&["replay", "fixture", "zswap"])
+                .help("show a machine running `ktop --serve` instead of this one"),
+        )
+        .arg(
+            Arg::with_name("iterations")
+                .short("n")
+                .long("iterations")
+                .takes_value(true)
+                .help("how many snapshots to print in batch mode. defaults to forever"),
         ).arg(
             Arg::with_name("show threads")
                 .short("t")
@@ -101,41 +311,138 @@ fn main() -> Result<(), Box<dyn Error>> {
         )
         .get_matches();
 
-    // TODO: pass the config file location to confy
-    // let myfile = matches.value_of("file").unwrap_or("input.txt");
-    // println!("The file passed is: {}", myfile);
-    let app_config = config::create_config_from_matches(matches);
-    // take care of the permissions first
-    let can_use_debugfs = app_config.can_use_debugfs
-        && match debug_permissions::can_read_debug() {
+    if matches.is_present("zswap helper") {
+        return Ok(zswap_helper::run(
+            matches.value_of("debugfs path").map(Path::new),
+        )?);
+    }
+    let app_config = match config::create_config_from_matches(&matches) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("ktop: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let debugfs_path = app_config
+        .debugfs_path
+        .clone()
+        .or_else(debug_permissions::find_debugfs)
+        .unwrap_or_else(|| PathBuf::from(debug_permissions::DEFAULT_DEBUG_DIR));
+    let replay = match &app_config.replay {
+        Some(path) => match Replay::open(path) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                eprintln!("ktop: could not open {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let recorder = match &app_config.record {
+        Some(path) => match Recorder::create(path) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                eprintln!("ktop: could not create {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let token = match &app_config.token {
+        Some(path) => match remote::read_token(path) {
+            Ok(token) => Some(token),
+            Err(e) => {
+                eprintln!("ktop: could not read {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    if let Some(addr) = &app_config.serve {
+        // there's no one to type a sudo password, so zswap stats need debugfs readable by the agent
+        let zswap_dir = match app_config.can_use_debugfs {
+            true => match debug_permissions::can_read_debug(&debugfs_path) {
+                DebugfsStatus::MountedAndReadable => Some(debugfs_path.clone()),
+                _ => {
+                    eprintln!(
+                        "{} can't be read - run the agent as root to serve zswap stats",
+                        debugfs_path.display()
+                    );
+                    None
+                }
+            },
+            false => None,
+        };
+        if let Err(e) = remote::serve(addr, app_config.allow_actions, token, zswap_dir) {
+            eprintln!("ktop: could not serve on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    // take care of the permissions first, while sudo can still ask for a password
+    let zswap_source = match app_config.can_use_debugfs {
+        false => None,
+        true => match debug_permissions::can_read_debug(&debugfs_path) {
+            DebugfsStatus::MountedAndReadable => Some(ZswapSource::Debugfs(debugfs_path.clone())),
             DebugfsStatus::NoPermissions => {
-                debug_permissions::set_debug_permissions();
-                matches!(
-                    debug_permissions::can_read_debug(),
-                    DebugfsStatus::MountedAndReadable
-                )
+                eprintln!(
+                    "{} is only readable by root - starting `ktop --zswap-helper` with sudo to read the zswap stats",
+                    debugfs_path.display()
+                );
+                match ZswapHelper::spawn(&debugfs_path) {
+                    Ok(helper) => Some(ZswapSource::Helper(helper)),
+                    Err(e) => {
+                        eprintln!("could not start the zswap helper: {}", e);
+                        None
+                    }
+                }
             }
-            DebugfsStatus::MountedAndReadable => true,
             DebugfsStatus::NotMounted => {
-                println!("Debugfs not found at TODO!");
-                false
+                eprintln!("Debugfs not found at {}", debugfs_path.display());
+                None
             }
-        };
-    let mut app_state = AppState {
-        can_use_debugfs,
-        headers: vec![
-            ColumnType::PID,
-            ColumnType::RUNTIME,
-            ColumnType::CPU,
-            ColumnType::MEMORY,
-            ColumnType::MEMORY_SWAP,
-            ColumnType::NAME,
-        ],
-        sorting_by: Some(ColumnType::CPU),
-        sorting_column_index: 2,
-        vminfo: vmstat_info::new(),
-        show_threads: app_config.show_threads,
+        },
+    };
+    let show_zswap = zswap_source.is_some();
+    let source: Box<dyn MetricsSource> = match (&app_config.fixture, &app_config.connect) {
+        (Some(dir), _) => match FixtureSource::open(dir) {
+            Ok(fixture) => Box::new(fixture),
+            Err(e) => {
+                eprintln!("ktop: could not open {}: {}", dir.display(), e);
+                std::process::exit(1);
+            }
+        },
+        (None, Some(addr)) => match RemoteSource::connect(addr, token) {
+            Ok(remote) => Box::new(remote),
+            Err(e) => {
+                eprintln!("ktop: could not connect to {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => Box::new(LiveSource::new(zswap_source)),
     };
+    let mut app_state = AppState::new(&app_config, source);
+    app_state.show_zswap = show_zswap;
+    app_state.debugfs_path = debugfs_path;
+    if let Some(replay) = &replay {
+        app_state.history = Histories::new(app_config.history_window, replay.interval());
+    }
+    app_state.read_all_fields |= recorder.is_some();
+    app_state.recorder = recorder;
+    app_state.replay = replay;
+
+    if app_config.batch {
+        if let Err(e) = batch::run(
+            &mut app_state,
+            app_config.format,
+            app_config.iterations,
+            app_config.delay,
+        ) {
+            eprintln!("ktop: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode()?;
@@ -144,152 +451,624 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut sys = System::new_all();
-    sys.refresh_all();
     let config = Config {
-        tick_rate: Duration::from_millis(app_config.delay * 1000),
+        tick_rate: match app_state.replay {
+            // the replay clock runs on its own, this is just how often it's checked
+            Some(_) => REPLAY_TICK,
+            None => Duration::from_millis(app_config.delay * 1000),
+        },
         ..Default::default()
     };
-    let events = Events::with_config(config);
+    let mut events = Events::with_config(config);
+    // 'q' is handled below, and dialogs need to be able to take it as input
+    events.disable_exit_key();
     let mut table = StatefulTable::new(vec![]);
+    if app_state.replay.is_some() {
+        show_replay_frame(&mut table, &mut app_state, false);
+    } else {
+        refresh_all(&mut table, &mut app_state);
+    }
+    // recordings and fixtures bring their own zswap stats
+    if app_state.snapshot.system.zswap.is_some() {
+        app_state.show_zswap = true;
+    }
     // Input
     loop {
         terminal.draw(|f| {
+            let meters = MeterWidget::new(&app_state.snapshot.system, app_state.cpu_breakdown);
+            // the meters get the rows they need, but never more than half the screen
+            let meters_height = meters.height(f.size().width).min(f.size().height / 2);
+            let mut constraints = vec![Constraint::Length(meters_height)];
+            if app_state.show_graphs {
+                constraints.push(Constraint::Percentage(30));
+            }
+            if app_state.show_zswap {
+                constraints.push(Constraint::Length(ZSWAP_PANEL_LINES + 2));
+            }
+            constraints.push(Constraint::Min(0));
             let rects = Layout::default()
-                .constraints([Constraint::Percentage(20), Constraint::Percentage(80)].as_ref())
+                .constraints(constraints)
                 .margin(0)
                 .split(f.size());
+            // the process table is always the last panel
+            let table_area = rects[rects.len() - 1];
 
-            let selected_style = Style::default().add_modifier(Modifier::REVERSED);
-            let normal_style = Style::default().bg(Color::Blue);
-
-            // main process table
-            let header_cells = app_state.headers.iter().map(|h| {
-                let color = if let Some(sorting_key) = &app_state.sorting_by {
-                    if h == sorting_key {
-                        Color::Green
-                    } else {
-                        Color::Red
-                    }
-                } else {
-                    Color::Red
-                };
-                Cell::from(h.value()).style(Style::default().fg(color))
-            });
-            let header = Row::new(header_cells)
-                .style(normal_style)
-                .height(1)
-                .bottom_margin(0);
-            let rows = table.items.iter().map(|item| {
-                let cells = item.iter().map(|this_span| Cell::from(this_span.clone()));
-                Row::new(cells).height(1).bottom_margin(0)
-            });
-            let title = match app_state.show_threads {
-                true => "Processes and threads",
-                false => "Processes",
-            };
-            let t = Table::new(rows)
-                .header(header)
-                .block(Block::default().borders(Borders::ALL).title(title))
-                .highlight_style(selected_style)
-                .highlight_symbol(">> ")
-                .widths(&[
-                    // TODO: make this part of appconfig headers
-                    Constraint::Length(8),
-                    Constraint::Length(9),
-                    Constraint::Length(7),
-                    Constraint::Length(8),
-                    Constraint::Length(8),
-                    Constraint::Min(20),
-                ]);
-            let meter = MeterWidget {
-                cpu_percent: sys.get_global_processor_info().get_cpu_usage() / 100f32,
-                cpu_system_percent: sys.get_global_processor_info().get_system_percent() / 100f32,
-                memory_percent: sys.get_used_memory() as f32 / sys.get_total_memory() as f32,
-                memory_used: sys.get_used_memory(),
-                swap_percent: sys.get_used_swap() as f32 / sys.get_total_swap() as f32,
-                total_swap: sys.get_total_swap(),
-                zswap_stats: match app_state.can_use_debugfs {
-                    true => match read_zswap_stats() {
-                        Ok(r) => Some(r),
-                        Err(_) => None,
+            draw_process_table(f, table_area, &mut table, &app_state);
+            f.render_widget(meters, rects[0]);
+            // optional panels go between the meters and the table, in order
+            let mut panel = 1;
+            if app_state.show_graphs {
+                f.render_widget(
+                    HistoryWidget {
+                        histories: &app_state.history,
                     },
-                    false => None,
-                },
-                swap_in: app_state.vminfo.swap_in,
-                swap_out: app_state.vminfo.swap_out,
-            };
-            f.render_stateful_widget(t, rects[1], &mut table.state);
-            f.render_widget(meter, rects[0]);
+                    rects[panel],
+                );
+                panel += 1;
+            }
+            if app_state.show_zswap {
+                f.render_widget(
+                    ZswapWidget {
+                        system: &app_state.snapshot.system,
+                        debugfs_path: &app_state.debugfs_path,
+                        page_size: proc_stat::page_size(),
+                    },
+                    rects[panel],
+                );
+            }
+            if let Some(dialog) = &mut app_state.dialog {
+                dialog.render(f);
+            }
         })?;
-        if app_config.run_once {
-            break;
-        }; // TODO: don't clear screen
         match events.next()? {
-            Event::Input(input) => match input {
-                Key::Char('q') => {
-                    break;
+            Event::Input(input) => {
+                app_state.status_message = None;
+                if let Some(dialog) = &mut app_state.dialog {
+                    match dialog.handle_key(input) {
+                        DialogAction::None => {}
+                        DialogAction::Close => app_state.dialog = None,
+                        DialogAction::SendSignal(id, signal) => {
+                            app_state.dialog = None;
+                            if let Err(e) = app_state.source.send_signal(id.pid, id.tid, signal) {
+                                app_state.status_message = Some(format!(
+                                    "Could not signal {}: {}",
+                                    id.tid.unwrap_or(id.pid),
+                                    e
+                                ));
+                            }
+                            refresh_all(&mut table, &mut app_state);
+                        }
+                        DialogAction::SetAffinity(id, cpus) => {
+                            app_state.dialog = None;
+                            let pid = id.tid.unwrap_or(id.pid);
+                            match app_state.source.set_affinity(id.pid, id.tid, &cpus) {
+                                Ok(()) => refresh_all(&mut table, &mut app_state),
+                                Err(e) => {
+                                    app_state.status_message =
+                                        Some(format!("Could not set affinity of {}: {}", pid, e))
+                                }
+                            }
+                        }
+                    }
+                    continue;
                 }
-                Key::Down => {
-                    table.next();
+                if app_state.query_input.is_some() {
+                    handle_query_key(input, &mut table, &mut app_state);
+                    continue;
                 }
-                Key::Up => {
-                    table.previous();
+                if app_state.replay.is_some()
+                    && handle_replay_key(input, &mut table, &mut app_state)
+                {
+                    continue;
                 }
-                Key::Esc => {
-                    if table.state.selected().is_some() {
-                        table.unselect();
-                    } else if app_state.sorting_by.is_some() {
-                        app_state.sorting_by = None;
-                        refresh_all(&mut sys, &mut table, &mut app_state);
+                match input {
+                    Key::Char('q') => {
+                        break;
                     }
-                }
-                Key::Right => {
-                    if app_state.sorting_column_index + 1 >= app_state.headers.len() {
-                        app_state.sorting_column_index = 0;
-                    } else {
-                        app_state.sorting_column_index += 1;
+                    Key::Down => {
+                        table.next();
                     }
-                    app_state.sorting_by = Some(app_state.headers[app_state.sorting_column_index]);
-                    refresh_all(&mut sys, &mut table, &mut app_state);
-                }
-                Key::Left => {
-                    if app_state.sorting_column_index == 0 {
-                        app_state.sorting_column_index = app_state.headers.len() - 1;
-                    } else {
-                        app_state.sorting_column_index -= 1;
+                    Key::Up => {
+                        table.previous();
+                    }
+                    Key::Esc => {
+                        if app_state.search.is_some() {
+                            app_state.search = None;
+                            update_table(&mut table, &mut app_state);
+                        } else if table.state.selected().is_some() {
+                            table.unselect();
+                        } else if app_state.sorting_by.is_some() {
+                            app_state.sorting_by = None;
+                            refresh_all(&mut table, &mut app_state);
+                        }
+                    }
+                    Key::Right => {
+                        if app_state.sorting_column_index + 1 >= app_state.headers.len() {
+                            app_state.sorting_column_index = 0;
+                        } else {
+                            app_state.sorting_column_index += 1;
+                        }
+                        app_state.sorting_by =
+                            Some(app_state.headers[app_state.sorting_column_index].kind);
+                        refresh_all(&mut table, &mut app_state);
+                    }
+                    Key::Left => {
+                        if app_state.sorting_column_index == 0 {
+                            app_state.sorting_column_index = app_state.headers.len() - 1;
+                        } else {
+                            app_state.sorting_column_index -= 1;
+                        }
+                        app_state.sorting_by =
+                            Some(app_state.headers[app_state.sorting_column_index].kind);
+                        refresh_all(&mut table, &mut app_state);
+                    }
+                    Key::Char('t') => {
+                        // show/hide threads
+                        app_state.show_threads = !app_state.show_threads;
+                        refresh_all(&mut table, &mut app_state);
+                    }
+                    Key::Char('g') => app_state.show_graphs = !app_state.show_graphs,
+                    Key::Char('z') => app_state.show_zswap = !app_state.show_zswap,
+                    Key::F(5) => {
+                        app_state.tree_view = !app_state.tree_view;
+                        refresh_all(&mut table, &mut app_state);
+                    }
+                    Key::Char('-') | Key::Char('+') if app_state.tree_view => {
+                        // collapse/expand the subtree under the selected row
+                        if let Some(id) = table
+                            .state
+                            .selected()
+                            .and_then(|i| app_state.row_ids.get(i))
+                        {
+                            let pid = id.pid;
+                            if input == Key::Char('-') {
+                                app_state.collapsed.insert(pid);
+                            } else {
+                                app_state.collapsed.remove(&pid);
+                            }
+                            refresh_all(&mut table, &mut app_state);
+                        }
+                    }
+                    Key::Char('/') => {
+                        app_state.query_input = Some(QueryInput {
+                            kind: QueryKind::Search,
+                            text: String::new(),
+                        });
+                    }
+                    Key::Char('\\') => {
+                        app_state.query_input = Some(QueryInput {
+                            kind: QueryKind::Filter,
+                            text: app_state.filter_text.clone(),
+                        });
+                    }
+                    Key::Char('n') | Key::Char('N') if app_state.search.is_some() => {
+                        let len = app_state.search_hits.len();
+                        if len > 0 {
+                            // with nothing selected, n starts at the top and N at the bottom
+                            let hit = match (input, table.state.selected()) {
+                                (Key::Char('n'), Some(selected)) => {
+                                    find_search_hit(&app_state.search_hits, selected + 1, true)
+                                }
+                                (Key::Char('n'), None) => {
+                                    find_search_hit(&app_state.search_hits, 0, true)
+                                }
+                                (_, Some(selected)) => find_search_hit(
+                                    &app_state.search_hits,
+                                    selected + len - 1,
+                                    false,
+                                ),
+                                (_, None) => {
+                                    find_search_hit(&app_state.search_hits, len - 1, false)
+                                }
+                            };
+                            if hit.is_some() {
+                                table.state.select(hit);
+                            }
+                        }
                     }
-                    app_state.sorting_by = Some(app_state.headers[app_state.sorting_column_index]);
-                    refresh_all(&mut sys, &mut table, &mut app_state);
+                    Key::Char('k') | Key::F(9) | Key::F(7) | Key::F(8) | Key::Char('a')
+                        if !can_change_processes(&app_state) =>
+                    {
+                        app_state.status_message = Some(String::from(
+                            "Only processes on this machine, or on an agent started with --allow-actions, can be changed",
+                        ));
+                    }
+                    Key::Char('k') | Key::F(9) => {
+                        if let Some(id) = selected_row(&table, &app_state) {
+                            let name = process_name(&app_state.snapshot, &id);
+                            app_state.dialog = Some(Dialog::signal_picker(id, name));
+                        }
+                    }
+                    Key::F(7) | Key::F(8) => {
+                        // F7 raises the priority (lower nice), F8 lowers it
+                        if let Some(id) = selected_row(&table, &app_state) {
+                            let pid = id.tid.unwrap_or(id.pid);
+                            let change = match input {
+                                Key::F(7) => -1,
+                                _ => 1,
+                            };
+                            match app_state.source.renice(id.pid, id.tid, change) {
+                                Ok(()) => refresh_all(&mut table, &mut app_state),
+                                Err(e) => {
+                                    app_state.status_message =
+                                        Some(format!("Could not renice {}: {}", pid, e))
+                                }
+                            }
+                        }
+                    }
+                    Key::Char('a') => {
+                        if let Some(id) = selected_row(&table, &app_state) {
+                            let pid = id.tid.unwrap_or(id.pid);
+                            let num_cpus = app_state.snapshot.system.per_cpu_percent.len();
+                            match app_state.source.get_affinity(id.pid, id.tid, num_cpus) {
+                                Ok(cpus) => {
+                                    let name = process_name(&app_state.snapshot, &id);
+                                    app_state.dialog = Some(Dialog::affinity(id, name, cpus));
+                                }
+                                Err(e) => {
+                                    app_state.status_message =
+                                        Some(format!("Could not get affinity of {}: {}", pid, e))
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
                 }
-                Key::Char('t') => {
-                    // show/hide threads
-                    app_state.show_threads = !app_state.show_threads;
-                    refresh_all(&mut sys, &mut table, &mut app_state);
+            }
+            Event::Tick => match app_state.replay.as_mut().map(|r| r.tick()) {
+                Some(true) => show_replay_frame(&mut table, &mut app_state, false),
+                Some(false) => {}
+                None => {
+                    refresh_all(&mut table, &mut app_state);
+                    record_history(&mut app_state);
+                    record_snapshot(&mut app_state);
                 }
-                _ => {}
             },
-            Event::Tick => {
-                // only refresh what we use
-                // sys.refresh_all();
-                refresh_all(&mut sys, &mut table, &mut app_state);
+        }
+    }
+
+    Ok(())
+}
+
+fn selected_row(table: &StatefulTable<'_>, app_state: &AppState) -> Option<RowId> {
+    table
+        .state
+        .selected()
+        .and_then(|i| app_state.row_ids.get(i))
+        .copied()
+}
+
+fn process_name(snapshot: &Snapshot, id: &RowId) -> String {
+    snapshot
+        .processes
+        .iter()
+        .find(|p| p.pid == id.pid && p.tid == id.tid)
+        .map_or(String::from("?"), |p| p.name.clone())
+}
+
+/// typing in the search/filter box. the table updates as you type
+fn handle_query_key(key: Key, table: &mut StatefulTable<'_>, app_state: &mut AppState) {
+    let mut input = match app_state.query_input.take() {
+        Some(input) => input,
+        None => return,
+    };
+    match key {
+        Key::Esc => {
+            // cancelling throws the query away
+            match input.kind {
+                QueryKind::Search => app_state.search = None,
+                QueryKind::Filter => {
+                    app_state.filter = None;
+                    app_state.filter_text.clear();
+                }
+            }
+            update_table(table, app_state);
+            return;
+        }
+        // keep the query, close the box
+        Key::Char('\n') => return,
+        Key::Backspace => {
+            input.text.pop();
+        }
+        Key::Char(c) => input.text.push(c),
+        _ => {}
+    }
+    match ProcessFilter::parse(&input.text) {
+        Ok(query) => match input.kind {
+            QueryKind::Search => app_state.search = query,
+            QueryKind::Filter => {
+                app_state.filter = query;
+                app_state.filter_text = input.text.clone();
+            }
+        },
+        // half typed regexes end up here - keep the last good query until it's fixed
+        Err(e) => app_state.status_message = Some(e),
+    }
+    let kind = input.kind;
+    app_state.query_input = Some(input);
+    update_table(table, app_state);
+    if kind == QueryKind::Search {
+        let start = table.state.selected().unwrap_or(0);
+        if let Some(hit) = find_search_hit(&app_state.search_hits, start, true) {
+            table.state.select(Some(hit));
+        }
+    }
+}
+
+/// the first row matching the search, starting at `start` and wrapping around
+fn find_search_hit(hits: &[bool], start: usize, forward: bool) -> Option<usize> {
+    let len = hits.len();
+    (0..len)
+        .map(|i| match forward {
+            true => (start + i) % len,
+            false => (start + len - i) % len,
+        })
+        .find(|&i| hits[i])
+}
+
+/// a replay keeps showing the same frame, but the table is still rebuilt, e.g. when the sorting changes
+fn refresh_all(table: &mut StatefulTable<'_>, app_state: &mut AppState) {
+    if app_state.replay.is_none() {
+        // the last snapshot stays up. an agent that went away is tried again on the next refresh
+        match refresh_sources(app_state) {
+            Ok(()) if app_state.refresh_failed => {
+                app_state.status_message = None;
+                app_state.refresh_failed = false;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                app_state.status_message = Some(e.to_string());
+                app_state.refresh_failed = true;
             }
         }
     }
+    update_table(table, app_state);
+}
 
+/// re-reads everything the meters and the process table are built from
+pub fn refresh_sources(app_state: &mut AppState) -> io::Result<()> {
+    let options = SnapshotOptions::new(app_state);
+    app_state.snapshot = app_state.source.refresh(&options)?;
+    // don't hide a newer message, like a failed kill
+    if let (Some(e), None) = (
+        &app_state.snapshot.system.zswap_error,
+        &app_state.status_message,
+    ) {
+        app_state.status_message = Some(format!("zswap: {}", e));
+    }
     Ok(())
 }
 
-fn refresh_all(sys: &mut System, mut table: &mut StatefulTable<'_>, app_state: &mut AppState) {
-    sys.refresh_cpu();
-    sys.refresh_processes();
-    sys.refresh_memory();
-    app_state.vminfo.update();
-    let processes = sys.get_processes();
-    table.items = processes::get_process_vec(processes, &app_state);
-    if let Some(index) = table.state.selected() {
-        if index >= table.items.len() - 1 {
-            table.state.select(Some(table.items.len() - 1));
+/// false when replaying, showing a fixture, or connected to an agent that
+/// doesn't allow it
+fn can_change_processes(app_state: &AppState) -> bool {
+    app_state.replay.is_none() && app_state.source.can_change_processes()
+}
+
+/// --record. a failed write stops the recording, rather than the UI
+fn record_snapshot(app_state: &mut AppState) {
+    if let Some(recorder) = &mut app_state.recorder {
+        if let Err(e) = recorder.write(&app_state.snapshot) {
+            app_state.status_message = Some(format!("Recording stopped: {}", e));
+            app_state.recorder = None;
+        }
+    }
+}
+
+/// space, , . [ ] Home End < and >. false if the key isn't one of them
+fn handle_replay_key(key: Key, table: &mut StatefulTable<'_>, app_state: &mut AppState) -> bool {
+    let replay = match &mut app_state.replay {
+        Some(replay) => replay,
+        None => return false,
+    };
+    // (whether there's a new frame, whether it skipped over some)
+    let (moved, jumped) = match key {
+        Key::Char(' ') => (replay.toggle_pause(), true),
+        Key::Char('.') => (replay.step(true), false),
+        Key::Char(',') => (replay.step(false), true),
+        Key::Char(']') => (replay.seek(SEEK_STEP), true),
+        Key::Char('[') => (replay.seek(-SEEK_STEP), true),
+        Key::Home => (replay.seek_to_start(), true),
+        Key::End => (replay.seek_to_end(), true),
+        Key::Char('>') => {
+            replay.faster();
+            (false, false)
+        }
+        Key::Char('<') => {
+            replay.slower();
+            (false, false)
+        }
+        _ => return false,
+    };
+    if moved {
+        show_replay_frame(table, app_state, jumped);
+    }
+    true
+}
+
+/// shows the frame the replay is on. the graphs start over after a jump, since
+/// they'd be mixing up two points in time otherwise
+fn show_replay_frame(table: &mut StatefulTable<'_>, app_state: &mut AppState, jumped: bool) {
+    let snapshot = match app_state.replay.as_mut().map(|r| r.load()) {
+        Some(Ok(snapshot)) => snapshot,
+        Some(Err(e)) => {
+            app_state.status_message = Some(format!("Could not read the recording: {}", e));
+            return;
         }
+        None => return,
+    };
+    app_state.snapshot = snapshot;
+    if jumped {
+        let history = &app_state.history;
+        app_state.history = Histories::new(history.window(), history.interval);
+    }
+    record_history(app_state);
+    update_table(table, app_state);
+}
+
+/// adds a sample to the graphs. only done on ticks, so the samples stay evenly spaced
+fn record_history(app_state: &mut AppState) {
+    let percent = |used: u64, total: u64| match total {
+        0 => 0.0,
+        total => used as f64 / total as f64 * 100.0,
+    };
+    let system = &app_state.snapshot.system;
+    let history = &mut app_state.history;
+    history.cpu.push(system.cpu_percent as f64);
+    history
+        .memory
+        .push(percent(system.memory_used, system.memory_total));
+    history
+        .swap
+        .push(percent(system.swap_used, system.swap_total));
+    history.swap_in.push(system.vmstat.swap_in.round() as u64);
+    history.swap_out.push(system.vmstat.swap_out.round() as u64);
+}
+
+/// the process table, with the search box and status message over its bottom border
+fn draw_process_table<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    table: &mut StatefulTable<'_>,
+    app_state: &AppState,
+) {
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+    let normal_style = Style::default().bg(Color::Blue);
+
+    // main process table
+    let header_cells = app_state.headers.iter().map(|h| {
+        let color = if let Some(sorting_key) = &app_state.sorting_by {
+            if h.kind == *sorting_key {
+                Color::Green
+            } else {
+                Color::Red
+            }
+        } else {
+            Color::Red
+        };
+        Cell::from(h.kind.value()).style(Style::default().fg(color))
+    });
+    let header = Row::new(header_cells)
+        .style(normal_style)
+        .height(1)
+        .bottom_margin(0);
+    let search_hits = &app_state.search_hits;
+    let rows = table.items.iter().enumerate().map(|(i, item)| {
+        let cells = item.iter().map(|this_span| Cell::from(this_span.clone()));
+        let style = match search_hits.get(i) {
+            Some(true) => Style::default().bg(Color::Yellow).fg(Color::Black),
+            _ => Style::default(),
+        };
+        Row::new(cells).style(style).height(1).bottom_margin(0)
+    });
+    let title = match (app_state.tree_view, app_state.show_threads) {
+        (true, true) => "Process tree with threads",
+        (true, false) => "Process tree",
+        (false, true) => "Processes and threads",
+        (false, false) => "Processes",
+    };
+    let mut title = match app_state.filter {
+        Some(_) => format!("{} (filter: {})", title, app_state.filter_text),
+        None => title.to_string(),
+    };
+    if let Some(name) = app_state.source.name() {
+        title = format!("{} on {}", title, name);
+    }
+    if let Some(replay) = &app_state.replay {
+        title = format!("{} - {}", title, replay.status());
+    }
+    if app_state.recorder.is_some() {
+        title.push_str(" - recording");
+    }
+    // inside the borders, and after the highlight symbol
+    let widths = fitting_widths(&app_state.headers, area.width.saturating_sub(2 + 3));
+    let t = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(selected_style)
+        .highlight_symbol(">> ")
+        .widths(&widths);
+    f.render_stateful_widget(t, area, &mut table.state);
+    // the search box and status messages are drawn over the bottom border of the table
+    if area.height > 0 && area.width > 2 {
+        let line = Rect::new(area.x + 1, area.bottom() - 1, area.width - 2, 1);
+        let mut text = vec![];
+        if let Some(input) = &app_state.query_input {
+            text.push(Span::styled(
+                format!("{}{}_ ", input.prompt(), input.text),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        }
+        if let Some(message) = &app_state.status_message {
+            text.push(Span::styled(
+                message.clone(),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        if !text.is_empty() {
+            f.render_widget(Paragraph::new(Spans::from(text)), line);
+        }
+    }
+}
+
+/// the widths of the columns that fit in `width`. tui draws past the edge of
+/// the table when they don't all fit, so the rest are left out, and their cells
+/// aren't drawn. the last one that fits gets whatever is left
+fn fitting_widths(columns: &[Column], width: u16) -> Vec<Constraint> {
+    let mut left = width;
+    let mut widths = vec![];
+    for column in columns {
+        if left == 0 {
+            break;
+        }
+        let constraint = column.constraint();
+        let needed = match constraint {
+            Constraint::Length(w) | Constraint::Min(w) => w,
+            _ => 0,
+        };
+        widths.push(match needed <= left {
+            true => constraint,
+            false => Constraint::Length(left),
+        });
+        // and a space between columns
+        left = left.saturating_sub(needed + 1);
+    }
+    widths
+}
+
+/// rebuilds the table from the last snapshot, without taking a new one
+fn update_table(table: &mut StatefulTable<'_>, app_state: &mut AppState) {
+    let selected = table
+        .state
+        .selected()
+        .and_then(|i| app_state.row_ids.get(i).copied().map(|id| (i, id)));
+    let rows = processes::get_process_vec(&app_state.snapshot.processes, app_state);
+    app_state.row_ids = rows.iter().map(|r| r.id).collect();
+    app_state.search_hits = rows.iter().map(|r| r.search_hit).collect();
+    table.items = rows.into_iter().map(|r| r.cells).collect();
+    if let Some((index, id)) = selected {
+        table
+            .state
+            .select(follow_selection(&app_state.row_ids, index, id));
+    }
+}
+
+/// where the selected row ended up after a refresh. follows the process as it
+/// moves around the table; if it's gone, falls back to its parent process (for
+/// threads) or whatever is now in the same spot
+fn follow_selection(row_ids: &[RowId], old_index: usize, id: RowId) -> Option<usize> {
+    if row_ids.is_empty() {
+        return None;
     }
+    let find = |id: RowId| row_ids.iter().position(|r| *r == id);
+    find(id)
+        .or_else(|| {
+            id.tid.and_then(|_| {
+                find(RowId {
+                    pid: id.pid,
+                    tid: None,
+                })
+            })
+        })
+        .or(Some(old_index.min(row_ids.len() - 1)))
 }
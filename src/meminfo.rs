@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// share of total memory in each state, 0 to 1. computed the same way htop does
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct MemoryBreakdown {
+    pub used: f32,
+    pub buffers: f32,
+    pub shared: f32,
+    pub cache: f32,
+}
+
+/// the /proc/meminfo fields we use, in KB
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemInfo {
+    pub total: u64,
+    pub free: u64,
+    pub buffers: u64,
+    pub cached: u64,
+    pub shmem: u64,
+    pub sreclaimable: u64,
+    /// free plus whatever could be reclaimed without swapping. older kernels
+    /// don't have it
+    pub available: Option<u64>,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+impl MemInfo {
+    pub fn breakdown(&self) -> MemoryBreakdown {
+        if self.total == 0 {
+            return MemoryBreakdown::default();
+        }
+        // reclaimable slab counts as cache; shared memory is counted in Cached,
+        // but shown as its own segment
+        let cache = (self.cached + self.sreclaimable).saturating_sub(self.shmem);
+        let used = self
+            .total
+            .saturating_sub(self.free + self.buffers + self.cached + self.sreclaimable);
+        let share = |kb: u64| kb as f32 / self.total as f32;
+        MemoryBreakdown {
+            used: share(used),
+            buffers: share(self.buffers),
+            shared: share(self.shmem),
+            cache: share(cache),
+        }
+    }
+}
+
+/// `root` is / for this machine
+pub fn read_meminfo(root: &Path) -> Option<MemInfo> {
+    let meminfo = fs::read_to_string(root.join("proc/meminfo")).ok()?;
+    let mut info = MemInfo::default();
+    for line in meminfo.lines() {
+        let mut fields = line.split_whitespace();
+        let (name, value) = match (fields.next(), fields.next().and_then(|v| v.parse().ok())) {
+            (Some(name), Some(value)) => (name, value),
+            _ => continue,
+        };
+        match name {
+            "MemTotal:" => info.total = value,
+            "MemFree:" => info.free = value,
+            "Buffers:" => info.buffers = value,
+            "Cached:" => info.cached = value,
+            "Shmem:" => info.shmem = value,
+            "SReclaimable:" => info.sreclaimable = value,
+            "MemAvailable:" => info.available = Some(value),
+            "SwapTotal:" => info.swap_total = value,
+            "SwapFree:" => info.swap_free = value,
+            _ => {}
+        }
+    }
+    Some(info)
+}
@@ -1,19 +1,18 @@
-use core::num;
-use std::{
-    time::{SystemTime, UNIX_EPOCH},
-    usize,
-};
-
-use bytefmt::format;
 use tui::{
     buffer::Buffer,
     layout::Rect,
-    style::{Color, Style},
-    symbols::bar,
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
     widgets::Widget,
 };
 
+use crate::cpu_stat::CpuBreakdown;
+use crate::meminfo::MemoryBreakdown;
+use crate::psi::{Pressure, PressureLine};
+use crate::snapshot::SystemRecord;
+use crate::util::format_seconds;
+use crate::vmstat::VmStatRates;
+use crate::zram::ZramDevice;
 use crate::zswap::ZswapStats;
 
 pub struct MeterWidget {
@@ -24,8 +23,19 @@ pub struct MeterWidget {
     pub swap_percent: f32,
     pub total_swap: u64,
     pub zswap_stats: Option<ZswapStats>,
-    pub(crate) swap_out: usize,
-    pub(crate) swap_in: usize,
+    pub(crate) vm_rates: VmStatRates,
+    /// usage of each logical cpu, 0 to 1
+    pub per_cpu: Vec<f32>,
+    /// if set, per cpu bars are split into user/system/iowait/... instead of one color
+    pub per_cpu_breakdown: Option<Vec<CpuBreakdown>>,
+    /// splits the CPU bar into user/system/iowait/...
+    pub cpu_breakdown: Option<CpuBreakdown>,
+    /// splits the MEM bar into used/buffers/shared/cache
+    pub memory_breakdown: Option<MemoryBreakdown>,
+    /// stall info from /proc/pressure. None hides the panel
+    pub pressure: Option<Pressure>,
+    /// one line each, under the swap activity
+    pub zram: Vec<ZramDevice>,
 }
 
 impl Default for MeterWidget {
@@ -38,19 +48,114 @@ impl Default for MeterWidget {
             total_swap: 0,
             zswap_stats: None,
             memory_used: 0,
-            swap_out: 0,
-            swap_in: 0,
+            vm_rates: VmStatRates::default(),
+            per_cpu: Vec::new(),
+            per_cpu_breakdown: None,
+            cpu_breakdown: None,
+            memory_breakdown: None,
+            pressure: None,
+            zram: Vec::new(),
+        }
+    }
+}
+
+impl MeterWidget {
+    /// the meters for one snapshot. `cpu_breakdown` splits the per cpu bars
+    /// into user/system/iowait/...
+    pub fn new(system: &SystemRecord, cpu_breakdown: bool) -> MeterWidget {
+        MeterWidget {
+            cpu_percent: system.cpu_percent / 100f32,
+            cpu_system_percent: system.cpu_system_percent / 100f32,
+            memory_percent: fraction(system.memory_used, system.memory_total),
+            memory_used: system.memory_used,
+            swap_percent: fraction(system.swap_used, system.swap_total),
+            total_swap: system.swap_total,
+            zswap_stats: system.zswap,
+            vm_rates: system.vmstat,
+            per_cpu: system.per_cpu_percent.iter().map(|p| p / 100f32).collect(),
+            per_cpu_breakdown: match cpu_breakdown {
+                true => Some(system.cores.clone()),
+                false => None,
+            },
+            cpu_breakdown: Some(system.cpu),
+            memory_breakdown: system.memory,
+            pressure: system.pressure,
+            zram: system.zram.clone(),
         }
     }
 }
 
+impl MeterWidget {
+    /// how many rows it takes to show everything at this width: the cpu, memory
+    /// and vmstat lines, the zram devices, and the per cpu bars
+    pub fn height(&self, width: u16) -> u16 {
+        let mut width = width;
+        let mut pressure_lines = 0;
+        if let Some(pressure) = &self.pressure {
+            if width >= PSI_PANEL_WIDTH * 2 {
+                width -= PSI_PANEL_WIDTH;
+                // a header, then cpu, mem, io and the full lines there are
+                pressure_lines =
+                    4 + pressure.memory.full.is_some() as u16 + pressure.io.full.is_some() as u16;
+            }
+        }
+        let count = self.per_cpu.len();
+        let per_row = match width / MIN_CORE_BAR_WIDTH {
+            columns if count < COMPACT_CORE_COUNT && columns > 0 => columns as usize,
+            // compact mode, one character per cpu
+            _ => width.max(1) as usize,
+        };
+        let core_rows = (count + per_row - 1) / per_row;
+        let lines = 4 + self.zram.len() + core_rows;
+        (lines.min(u16::MAX as usize) as u16).max(pressure_lines)
+    }
+}
+
+/// used / total, or 0 if there's nothing to use, like swap on a machine without any
+fn fraction(used: u64, total: u64) -> f32 {
+    match total {
+        0 => 0.0,
+        total => used as f32 / total as f32,
+    }
+}
+
 impl Widget for MeterWidget {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        // TODO: make the bars here
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        // the pressure panel takes the right edge, if there's room for it
+        let area = match &self.pressure {
+            Some(pressure) if area.width >= PSI_PANEL_WIDTH * 2 => {
+                let panel = Rect::new(
+                    area.right() - PSI_PANEL_WIDTH,
+                    area.top(),
+                    PSI_PANEL_WIDTH,
+                    area.height,
+                );
+                render_pressure(pressure, panel, buf);
+                Rect::new(
+                    area.left(),
+                    area.top(),
+                    area.width - PSI_PANEL_WIDTH,
+                    area.height,
+                )
+            }
+            _ => area,
+        };
+        let cpu_segments = match &self.cpu_breakdown {
+            Some(b) => cpu_segments(b),
+            None => vec![(self.cpu_percent, Style::default().fg(Color::Red))],
+        };
         buf.set_spans(
             area.left(),
             area.top(),
-            &make_bar(self.cpu_percent, area.width as usize / 2, "CPU".to_string()),
+            &make_bar(
+                self.cpu_percent,
+                &cpu_segments,
+                area.width as usize / 2,
+                "CPU".to_string(),
+            ),
             area.width / 2,
         );
         buf.set_spans(
@@ -58,52 +163,133 @@ impl Widget for MeterWidget {
             area.top(),
             &make_bar(
                 self.cpu_system_percent,
+                &[(
+                    self.cpu_system_percent,
+                    Style::default().fg(CPU_SYSTEM_COLOR),
+                )],
                 area.width as usize / 2,
                 "SCPU".to_string(),
             ),
             area.width / 2,
         );
+        if area.height < 2 {
+            return;
+        }
+        let memory_segments = match &self.memory_breakdown {
+            Some(b) => memory_segments(b),
+            None => vec![(self.memory_percent, Style::default().fg(Color::Red))],
+        };
         buf.set_spans(
             area.left(),
             area.top() + 1,
             &make_bar_with_label(
                 self.memory_percent,
+                &memory_segments,
                 area.width as usize / 2,
                 "MEM".to_string(),
                 bytefmt::format_base2(self.memory_used * 1000).replace('B', ""),
             ),
             area.width / 2,
         );
-        buf.set_spans(
-            area.left() + area.width / 2,
-            area.top() + 1,
-            &make_bar_with_label(
+        let swap = match self.total_swap {
+            0 => whole_label(
+                format!("{:1$}no swap", "SWAP", LABEL_WIDTH),
+                area.width as usize / 2,
+            ),
+            _ => make_bar_with_label(
                 self.swap_percent,
+                &[(self.swap_percent, Style::default().fg(Color::Red))],
                 area.width as usize / 2,
                 "SWAP".to_string(),
                 // this unit isn't correct - 1024 mb is displayed as 1.07 gb. for RAM, we want to use base 2, not base 10
                 // again, just make my own converter
                 // (self.total_swap).to_string()
-                match self.zswap_stats {
+                match &self.zswap_stats {
                     Some(stats) => {
                         format!(
                             "Disk: {} Compr: {} Raw: {}",
                             bytefmt::format_base2(stats.written_back_pages * 1000).replace('B', ""),
                             bytefmt::format_base2(stats.pool_total_size).replace('B', ""),
-                            bytefmt::format_base2((self.swap_percent * self.total_swap as f32 * 1000f32) as u64).replace('B', "")
+                            bytefmt::format_base2(
+                                (self.swap_percent * self.total_swap as f32 * 1000f32) as u64
+                            )
+                            .replace('B', "")
                         )
                     }
                     None => bytefmt::format_base2((self.total_swap * 1000) as u64).replace("B", ""),
                 },
             ),
+        };
+        buf.set_spans(
+            area.left() + area.width / 2,
+            area.top() + 1,
+            &swap,
             area.width / 2,
         );
-        buf.set_string(
+        if area.height < 3 {
+            return;
+        }
+        let rates = &self.vm_rates;
+        buf.set_stringn(
+            area.left(),
+            area.top() + 2,
+            format!(
+                "Swap in: {:.0}/s out: {:.0}/s  Paging in: {}/s out: {}/s  Faults: {:.0}/s major: {:.0}/s",
+                rates.swap_in,
+                rates.swap_out,
+                bytefmt::format_base2((rates.page_in * 1024.0) as u64).replace('B', ""),
+                bytefmt::format_base2((rates.page_out * 1024.0) as u64).replace('B', ""),
+                rates.faults,
+                rates.major_faults,
+            ),
+            area.width as usize,
+            Style::default(),
+        );
+        if area.height < 4 {
+            return;
+        }
+        let (x, _) = buf.set_stringn(
             area.left(),
             area.top() + 3,
-            format!("Swap in: {} out: {}", self.swap_in, self.swap_out),
+            format!(
+                "Reclaim scan: {:.0}/s steal: {:.0}/s  THP alloc: {:.0}/s split: {:.0}/s  ",
+                rates.scanned, rates.stolen, rates.thp_alloc, rates.thp_split,
+            ),
+            area.width as usize,
             Style::default(),
         );
+        // an oom kill is worth pointing out
+        let oom_style = match rates.oom_kills {
+            0 => Style::default(),
+            _ => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        };
+        if x < area.right() {
+            buf.set_stringn(
+                x,
+                area.top() + 3,
+                format!("OOM kills: {}", rates.oom_kills_total),
+                (area.right() - x) as usize,
+                oom_style,
+            );
+        }
+        let mut top = 4;
+        for device in &self.zram {
+            if top >= area.height {
+                break;
+            }
+            buf.set_spans(
+                area.left(),
+                area.top() + top,
+                &make_zram_line(device),
+                area.width,
+            );
+            top += 1;
+        }
+        if area.height > top {
+            let cores_area =
+                Rect::new(area.left(), area.top() + top, area.width, area.height - top);
+            self.render_cores(cores_area, buf);
+        }
 
         /*
         let start = SystemTime::now();
@@ -125,44 +311,322 @@ impl Widget for MeterWidget {
     }
 }
 
-fn make_bar<'a>(percentage: f32, width: usize, label: String) -> Spans<'a> {
-    make_bar_with_label(percentage, width, label, String::from(""))
+// colors for the per state cpu segments, same as htop
+const CPU_USER_COLOR: Color = Color::Green;
+const CPU_NICE_COLOR: Color = Color::Blue;
+const CPU_SYSTEM_COLOR: Color = Color::Red;
+const CPU_IOWAIT_COLOR: Color = Color::DarkGray;
+const CPU_IRQ_COLOR: Color = Color::Yellow;
+const CPU_STEAL_COLOR: Color = Color::Cyan;
+// and for memory
+const MEM_USED_COLOR: Color = Color::Green;
+const MEM_BUFFERS_COLOR: Color = Color::Blue;
+const MEM_SHARED_COLOR: Color = Color::Magenta;
+const MEM_CACHE_COLOR: Color = Color::Yellow;
+
+/// (fraction, style) for one part of a bar. segments are drawn left to right
+type Segment = (f32, Style);
+
+fn cpu_segments(b: &CpuBreakdown) -> Vec<Segment> {
+    vec![
+        (b.user, Style::default().fg(CPU_USER_COLOR)),
+        (b.nice, Style::default().fg(CPU_NICE_COLOR)),
+        (b.system, Style::default().fg(CPU_SYSTEM_COLOR)),
+        (b.iowait, Style::default().fg(CPU_IOWAIT_COLOR)),
+        (b.irq, Style::default().fg(CPU_IRQ_COLOR)),
+        (b.steal, Style::default().fg(CPU_STEAL_COLOR)),
+    ]
+}
+
+fn memory_segments(b: &MemoryBreakdown) -> Vec<Segment> {
+    vec![
+        (b.used, Style::default().fg(MEM_USED_COLOR)),
+        (b.buffers, Style::default().fg(MEM_BUFFERS_COLOR)),
+        (b.shared, Style::default().fg(MEM_SHARED_COLOR)),
+        (b.cache, Style::default().fg(MEM_CACHE_COLOR)),
+    ]
+}
+
+/// narrowest a labelled per cpu bar can be before we switch to compact mode
+const MIN_CORE_BAR_WIDTH: u16 = 12;
+/// past this many cpus, labelled bars are just noise
+const COMPACT_CORE_COUNT: usize = 64;
+
+impl MeterWidget {
+    /// the segments of the bar for cpu `i`
+    fn core_segments(&self, i: usize) -> Vec<Segment> {
+        match self.per_cpu_breakdown.as_ref().and_then(|b| b.get(i)) {
+            Some(b) => cpu_segments(b),
+            None => vec![(self.per_cpu[i], Style::default().fg(Color::Red))],
+        }
+    }
+
+    /// one bar per cpu, in as many columns as it takes to fit them all. if
+    /// they don't fit (or there are a lot of them), each cpu gets one character
+    fn render_cores(&self, area: Rect, buf: &mut Buffer) {
+        let count = self.per_cpu.len();
+        if count == 0 || area.height == 0 || area.width == 0 {
+            return;
+        }
+        let columns = (count + area.height as usize - 1) / area.height as usize;
+        let column_width = area.width / columns as u16;
+        if count >= COMPACT_CORE_COUNT || column_width < MIN_CORE_BAR_WIDTH {
+            self.render_cores_compact(area, buf);
+            return;
+        }
+        let rows = (count + columns - 1) / columns;
+        let label_width = (count - 1).to_string().len();
+        for i in 0..count {
+            let x = area.left() + (i / rows) as u16 * column_width;
+            let y = area.top() + (i % rows) as u16;
+            let spans = make_core_bar(
+                &format!("{:>1$}", i, label_width),
+                &self.core_segments(i),
+                // leave a gap between columns
+                column_width as usize - 1,
+            );
+            buf.set_spans(x, y, &spans, column_width - 1);
+        }
+    }
+
+    fn render_cores_compact(&self, area: Rect, buf: &mut Buffer) {
+        const LEVELS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+        let per_row = area.width as usize;
+        for (i, usage) in self.per_cpu.iter().enumerate() {
+            let (x, y) = ((i % per_row) as u16, (i / per_row) as u16);
+            if y >= area.height {
+                break;
+            }
+            let usage = usage.clamp(0.0, 1.0);
+            let level = LEVELS[f32::round(usage * 8.0) as usize];
+            let color = match usage {
+                u if u >= 0.9 => Color::Red,
+                u if u >= 0.5 => Color::Yellow,
+                _ => Color::Green,
+            };
+            buf.set_string(
+                area.left() + x,
+                area.top() + y,
+                level,
+                Style::default().fg(color).bg(Color::Black),
+            );
+        }
+    }
+}
+
+/// "zram0 zstd: 1.2G -> 300M (4.00x) ..."
+fn make_zram_line<'a>(device: &ZramDevice) -> Spans<'a> {
+    let size = |bytes: u64| bytefmt::format_base2(bytes).replace('B', "");
+    let ratio = match device.compression_ratio() {
+        Some(r) => format!("{:.2}x", r),
+        None => String::from("-"),
+    };
+    let mut spans = vec![
+        Span::styled(
+            format!("{} {}: ", device.name, device.algorithm),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!(
+            "{} -> {} ({}), {} used of {}  same {} huge {} pages  wb {} pages, {} read, {} written",
+            size(device.orig_data_size),
+            size(device.compr_data_size),
+            ratio,
+            size(device.mem_used_total),
+            size(device.disk_size),
+            device.same_pages,
+            device.huge_pages,
+            device.bd_count,
+            device.bd_reads,
+            device.bd_writes,
+        )),
+    ];
+    if device.failed_reads > 0 || device.failed_writes > 0 {
+        spans.push(Span::styled(
+            format!(
+                "  failed reads {} writes {}",
+                device.failed_reads, device.failed_writes
+            ),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    Spans::from(spans)
+}
+
+const PSI_PANEL_WIDTH: u16 = 46;
+const PSI_LABEL_WIDTH: usize = 9;
+
+/// one bar per pressure line, for the last 10s, followed by the 60s and 300s
+/// averages and the total stall time
+fn render_pressure(pressure: &Pressure, area: Rect, buf: &mut Buffer) {
+    // cpu full is always 0 system wide, so it's left out
+    let mut lines = vec![
+        ("cpu some", pressure.cpu.some),
+        ("mem some", pressure.memory.some),
+    ];
+    if let Some(full) = pressure.memory.full {
+        lines.push(("mem full", full));
+    }
+    lines.push(("io some", pressure.io.some));
+    if let Some(full) = pressure.io.full {
+        lines.push(("io full", full));
+    }
+
+    // leave a gap between the panel and the swap bar
+    let x = area.left() + 1;
+    let width = area.width as usize - 1;
+    let bar_width = width - PSI_LABEL_WIDTH - 2 - 6 - 6 - 7;
+    buf.set_stringn(
+        x,
+        area.top(),
+        format!(
+            "{:<label$}{:^bar$}{:>6}{:>6}{:>7}",
+            "PSI",
+            "avg10",
+            "60s",
+            "300s",
+            "total",
+            label = PSI_LABEL_WIDTH,
+            bar = bar_width + 2
+        ),
+        width,
+        Style::default().add_modifier(Modifier::BOLD),
+    );
+    for (row, (label, line)) in lines.iter().enumerate() {
+        let y = area.top() + 1 + row as u16;
+        if y >= area.bottom() {
+            break;
+        }
+        buf.set_spans(
+            x,
+            y,
+            &make_pressure_bar(label, line, bar_width),
+            width as u16,
+        );
+    }
+}
+
+fn make_pressure_bar<'a>(label: &str, line: &PressureLine, bar_width: usize) -> Spans<'a> {
+    let color = match line.avg10 {
+        p if p >= 50.0 => Color::Red,
+        p if p >= 10.0 => Color::Yellow,
+        _ => Color::Green,
+    };
+    let mut spans = vec![Span::raw(format!("{:<1$}[", label, PSI_LABEL_WIDTH))];
+    spans.extend(overlay_bar(
+        &[(line.avg10 / 100f32, Style::default().fg(color))],
+        bar_width,
+        &format!("{:.2}", line.avg10),
+    ));
+    spans.push(Span::raw(format!(
+        "]{:>6.2}{:>6.2}{:>7}",
+        line.avg60,
+        line.avg300,
+        format_seconds(line.total / 1_000_000)
+    )));
+    Spans::from(spans)
+}
+
+/// "3[|||||   12%]" - one labelled cpu bar
+fn make_core_bar<'a>(label: &str, segments: &[Segment], width: usize) -> Spans<'a> {
+    let total: f32 = segments.iter().map(|(fraction, _)| fraction).sum();
+    let percent_label = format!("{:.0}%", total * 100f32);
+    let bar_width = width.saturating_sub(label.len() + 2);
+    let mut spans = vec![Span::styled(format!("{}[", label), Style::default())];
+    spans.extend(overlay_bar(segments, bar_width, &percent_label));
+    spans.push(Span::styled("]", Style::default()));
+    Spans::from(spans)
+}
+
+fn make_bar<'a>(percentage: f32, segments: &[Segment], width: usize, label: String) -> Spans<'a> {
+    make_bar_with_label(percentage, segments, width, label, String::from(""))
 }
 const LABEL_WIDTH: usize = 5;
+/// "MEM  [|||||    1.2G 40.0%] ". as the bar gets narrower, the size in it is
+/// dropped, and then the bar goes too, leaving "MEM 40% ", and then nothing
 fn make_bar_with_label<'a>(
     percentage: f32,
+    segments: &[Segment],
     width: usize,
     label: String,
     inner_label_prefix: String,
 ) -> Spans<'a> {
-    // case 1: bar + space + label
-    //        |---|
-    // case 2: bar + label
-    //        |--------|   some (or all) of label is colored
-    // TODO: handle case 2. for now, the label hides the bar
-    let bar_width = width - LABEL_WIDTH - 2 - 2;
+    let percentage = match percentage.is_nan() {
+        true => 0.0,
+        false => percentage,
+    };
+    let bar_width = width.saturating_sub(LABEL_WIDTH + 2 + 2);
+    // too narrow for even the percentage
+    if bar_width < format!(" {:3.1}%", percentage * 100f32).len() {
+        return whole_label(format!("{} {:.0}%", label, percentage * 100f32), width);
+    }
     let percent_label = format!(
         "{:width$}{:3.1}%",
         inner_label_prefix,
         percentage * 100f32,
         width = inner_label_prefix.len() + 1
     );
-    let num_filled_blocks = f32::round(bar_width as f32 * percentage) as usize;
-    let mut bar_spans = vec![
-        Span::styled(format!("{:1$}[", label, LABEL_WIDTH), Style::default()),
-        Span::styled(
-            format!(
-                "{:1$}",
-                tui::symbols::line::VERTICAL
-                    .repeat(num_filled_blocks.min(bar_width - percent_label.len())),
-                bar_width - percent_label.len()
-            ),
-            // TODO: the blue is just for debugging
-            Style::default().fg(Color::Red),
-        ),
-    ];
-    bar_spans.push(Span::styled(percent_label, Style::default()));
+    // a cut off size would be read as a smaller one, so drop it entirely
+    let percent_label = match percent_label.chars().count() > bar_width {
+        true => format!("{:3.1}%", percentage * 100f32),
+        false => percent_label,
+    };
+    let mut bar_spans = vec![Span::styled(
+        format!("{:1$}[", label, LABEL_WIDTH),
+        Style::default(),
+    )];
+    bar_spans.extend(overlay_bar(segments, bar_width, &percent_label));
     bar_spans.push(Span::styled("] ", Style::default()));
 
     Spans::from(bar_spans)
 }
+
+/// `text`, or nothing if it doesn't fit in `width` with a space after it to
+/// keep it apart from the next meter. a cut off label would be misread
+fn whole_label<'a>(text: String, width: usize) -> Spans<'a> {
+    match text.chars().count() < width {
+        true => Spans::from(text),
+        false => Spans::default(),
+    }
+}
+
+/// draws the segments, then writes `text` right aligned on top of them. the
+/// parts of the text over a filled part of the bar take that segment's color
+fn overlay_bar<'a>(segments: &[Segment], width: usize, text: &str) -> Vec<Span<'a>> {
+    let bar_char = tui::symbols::line::VERTICAL.chars().next().unwrap_or('|');
+    let mut cells: Vec<(char, Style)> = Vec::with_capacity(width);
+    for (fraction, style) in segments {
+        let blocks = f32::round(width as f32 * fraction.max(0.0)) as usize;
+        let end = (cells.len() + blocks).min(width);
+        cells.resize(end, (bar_char, *style));
+    }
+    let filled = cells.len();
+    cells.resize(width, (' ', Style::default()));
+
+    // if the text doesn't fit, cut off the start - the end has the percentage
+    let text: Vec<char> = text.chars().collect();
+    let text = &text[text.len().saturating_sub(width)..];
+    let start = width - text.len();
+    for (i, c) in text.iter().enumerate() {
+        let style = match start + i < filled {
+            true => cells[start + i].1.add_modifier(Modifier::BOLD),
+            false => Style::default(),
+        };
+        cells[start + i] = (*c, style);
+    }
+
+    // one span per run of the same style
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_style = Style::default();
+    for (c, style) in cells {
+        if style != run_style && !run.is_empty() {
+            spans.push(Span::styled(std::mem::take(&mut run), run_style));
+        }
+        run_style = style;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(Span::styled(run, run_style));
+    }
+    spans
+}
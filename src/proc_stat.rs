@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// the bits of /proc/[pid]/stat and /proc/[pid]/statm that sysinfo doesn't give us
+#[derive(Debug, Clone)]
+pub struct ProcStat {
+    pub state: char,
+    /// 0 for init and kthreadd
+    pub ppid: i32,
+    pub tty_nr: i32,
+    pub priority: i64,
+    pub nice: i64,
+    pub num_threads: i64,
+    /// cpu time in user and kernel mode, in clock ticks
+    pub utime: u64,
+    pub stime: u64,
+    /// clock ticks after boot
+    pub start_time: u64,
+    /// shared memory, in KB
+    pub shared: u64,
+    /// Cpus_allowed_list from /proc/[pid]/status, like "0-3,6". only filled in
+    /// by read_cpus_allowed
+    pub cpus_allowed: String,
+}
+
+impl Default for ProcStat {
+    /// what we show when the stat file is gone (the process exited mid-refresh)
+    fn default() -> Self {
+        ProcStat {
+            state: '?',
+            ppid: 0,
+            tty_nr: 0,
+            priority: 0,
+            nice: 0,
+            num_threads: 0,
+            utime: 0,
+            stime: 0,
+            start_time: 0,
+            shared: 0,
+            cpus_allowed: String::from("?"),
+        }
+    }
+}
+
+/// /proc/[pid] under `root`, which is / for this machine. also works for thread
+/// ids on a live system, even though they aren't listed in /proc
+pub fn process_dir(root: &Path, pid: i32) -> PathBuf {
+    root.join("proc").join(pid.to_string())
+}
+
+/// reads stat and statm from a /proc/[pid] or /proc/[pid]/task/[tid] directory
+pub fn read_proc_stat(dir: &Path) -> Option<ProcStat> {
+    let stat = fs::read_to_string(dir.join("stat")).ok()?;
+    let mut proc_stat = parse_stat(&stat)?;
+    if let Ok(statm) = fs::read_to_string(dir.join("statm")) {
+        // size resident shared text lib data dt - all in pages
+        let shared_pages: u64 = statm
+            .split_whitespace()
+            .nth(2)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        proc_stat.shared = shared_pages * page_size() / 1024;
+    }
+    Some(proc_stat)
+}
+
+/// the cpus a process may run on, as a list like "0-3,6"
+pub fn read_cpus_allowed(dir: &Path) -> Option<String> {
+    read_status_field(dir, "Cpus_allowed_list:")
+}
+
+/// one "Name:  value" line of /proc/[pid]/status, without the name
+pub fn read_status_field(dir: &Path, name: &str) -> Option<String> {
+    let status = fs::read_to_string(dir.join("status")).ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with(name))
+        .map(|line| line[name.len()..].trim().to_string())
+}
+
+fn parse_stat(stat: &str) -> Option<ProcStat> {
+    // the process name is in parens and can contain spaces (or more parens), so
+    // skip to the last ')' before splitting
+    let after_name = &stat[stat.rfind(')')? + 1..];
+    let fields: Vec<&str> = after_name.split_whitespace().collect();
+    // fields[0] is the state, which is field 3 in proc(5)
+    let field = |i: usize| fields.get(i).and_then(|s| s.parse::<i64>().ok());
+    Some(ProcStat {
+        state: fields.first()?.chars().next()?,
+        ppid: field(1)? as i32,
+        tty_nr: field(4)? as i32,
+        priority: field(15)?,
+        nice: field(16)?,
+        num_threads: field(17)?,
+        utime: field(11)? as u64,
+        stime: field(12)? as u64,
+        start_time: field(19)? as u64,
+        shared: 0,
+        cpus_allowed: String::new(),
+    })
+}
+
+pub fn page_size() -> u64 {
+    // never fails on linux
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+/// turns a tty_nr from /proc/[pid]/stat into a name like "pts/3" or "tty1"
+pub fn tty_name(tty_nr: i32) -> String {
+    if tty_nr == 0 {
+        return String::from("?");
+    }
+    let tty_nr = tty_nr as u32;
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+    match major {
+        // unix98 pseudo terminals
+        136..=143 => format!("pts/{}", minor + (major - 136) * 256),
+        4 if minor < 64 => format!("tty{}", minor),
+        4 => format!("ttyS{}", minor - 64),
+        _ => format!("{}:{}", major, minor),
+    }
+}
+
+/// uid -> user name, from /etc/passwd. `root` is / for this machine
+pub fn read_user_names(root: &Path) -> HashMap<u32, String> {
+    let mut users = HashMap::new();
+    if let Ok(passwd) = fs::read_to_string(root.join("etc/passwd")) {
+        for line in passwd.lines() {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 3 {
+                continue;
+            }
+            if let Ok(uid) = fields[2].parse() {
+                users.insert(uid, fields[0].to_string());
+            }
+        }
+    }
+    users
+}
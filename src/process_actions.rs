@@ -0,0 +1,106 @@
+use std::io;
+
+/// the signals offered by the kill dialog, in the order they're listed
+pub const SIGNALS: [(&str, i32); 8] = [
+    ("TERM", libc::SIGTERM),
+    ("KILL", libc::SIGKILL),
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("STOP", libc::SIGSTOP),
+    ("CONT", libc::SIGCONT),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+];
+
+/// turns the errno from a failed syscall into something to show in the status line
+fn describe_error(e: io::Error) -> String {
+    match e.raw_os_error() {
+        Some(libc::EPERM) | Some(libc::EACCES) => String::from("permission denied"),
+        Some(libc::ESRCH) => String::from("no such process"),
+        _ => e.to_string(),
+    }
+}
+
+/// signals the whole process, or only the thread `tid` of it
+pub fn send_signal(pid: i32, tid: Option<i32>, signal: i32) -> Result<(), String> {
+    // sysinfo's kill() only says whether it worked, not why it didn't
+    let result = match tid {
+        // kill() with a tid would signal every thread of the process
+        Some(tid) => unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) },
+        None => unsafe { libc::kill(pid, signal) }.into(),
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(describe_error(io::Error::last_os_error())),
+    }
+}
+
+/// linux keeps the nice value and affinity of every thread separately, so
+/// changing a whole process means changing each of its `tasks`. threads that
+/// exit before they're reached are skipped
+fn for_each_task(tasks: &[i32], change: impl Fn(i32) -> libc::c_int) -> Result<(), String> {
+    let mut changed = false;
+    for &task in tasks {
+        if change(task) == 0 {
+            changed = true;
+            continue;
+        }
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(libc::ESRCH) {
+            return Err(describe_error(e));
+        }
+    }
+    match changed {
+        true => Ok(()),
+        false => Err(describe_error(io::Error::from_raw_os_error(libc::ESRCH))),
+    }
+}
+
+/// cpu_set_t only has room for this many cpus
+fn check_cpu_count(num_cpus: usize) -> Result<(), String> {
+    match num_cpus > libc::CPU_SETSIZE as usize {
+        true => Err(format!(
+            "only the first {} cpus can be pinned to",
+            libc::CPU_SETSIZE
+        )),
+        false => Ok(()),
+    }
+}
+
+/// the nice value of the given processes or threads
+pub fn renice(tasks: &[i32], nice: i64) -> Result<(), String> {
+    let nice = nice.clamp(-20, 19) as libc::c_int;
+    for_each_task(tasks, |task| unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, task as libc::id_t, nice)
+    })
+}
+
+/// which cpus the process (or thread, if given a tid) is allowed to run on,
+/// indexed by cpu number
+pub fn get_affinity(pid: i32, num_cpus: usize) -> Result<Vec<bool>, String> {
+    check_cpu_count(num_cpus)?;
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_getaffinity(pid, size, &mut set) } != 0 {
+        return Err(describe_error(io::Error::last_os_error()));
+    }
+    Ok((0..num_cpus)
+        .map(|cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect())
+}
+
+/// the cpus the given processes or threads are allowed to run on
+pub fn set_affinity(tasks: &[i32], cpus: &[bool]) -> Result<(), String> {
+    check_cpu_count(cpus.len())?;
+    if !cpus.iter().any(|allowed| *allowed) {
+        return Err(String::from("at least one cpu has to be allowed"));
+    }
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for (cpu, _) in cpus.iter().enumerate().filter(|(_, allowed)| **allowed) {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    for_each_task(tasks, |task| unsafe {
+        libc::sched_setaffinity(task, size, &set)
+    })
+}
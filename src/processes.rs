@@ -1,83 +1,252 @@
+use crate::columns::ColumnType;
+use crate::proc_stat::{process_dir, read_cpus_allowed, read_proc_stat, tty_name, ProcStat};
+use crate::snapshot::ProcessRecord;
+use crate::source::{SnapshotOptions, LIVE_ROOT};
 use crate::AppState;
-use crate::ColumnType;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
 use sysinfo::{Process, ProcessExt};
 use tui::style::{Color, Style};
 use tui::text::{Span, Spans};
 
+/// identifies a row of the process table. threads keep the pid of the process
+/// they belong to, so a process and its main thread don't get mixed up
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct RowId {
+    pub pid: i32,
+    pub tid: Option<i32>,
+}
+
+/// one row of the process table, and what it's showing
+pub struct ProcessRow<'a> {
+    pub id: RowId,
+    pub cells: Vec<Spans<'a>>,
+    /// matches the current search
+    pub search_hit: bool,
+}
+
+struct Entry<'p> {
+    id: RowId,
+    process: &'p ProcessRecord,
+    /// indentation glyphs drawn before the name in tree mode
+    tree_prefix: String,
+}
+
 fn get_threads_from_process_map(
     processes: &HashMap<i32, Process>,
     use_threads: bool,
-) -> Vec<&Process> {
+) -> Vec<(RowId, &Process)> {
     let mut all_threads = Vec::with_capacity(processes.len() * 2);
-    for (_, process) in processes {
-        all_threads.push(process);
+    for (pid, process) in processes {
+        all_threads.push((
+            RowId {
+                pid: *pid,
+                tid: None,
+            },
+            process,
+        ));
         if use_threads {
-            all_threads.append(&mut get_threads_from_process_map(&process.tasks, true));
+            for (tid, task) in &process.tasks {
+                all_threads.push((
+                    RowId {
+                        pid: *pid,
+                        tid: Some(*tid),
+                    },
+                    task,
+                ));
+            }
         }
     }
     all_threads
 }
 
-pub fn get_process_vec<'a>(
-    processes: &HashMap<i32, Process>,
-    app_state: &AppState,
-) -> Vec<Vec<Spans<'a>>> {
-    let mut all_threads = get_threads_from_process_map(processes, app_state.show_threads);
+fn compare_entries(a: &Entry, b: &Entry, sorting_key: ColumnType) -> Ordering {
+    let (a, b) = (a.process, b.process);
     // there has got to be a better way to do this
-    if let Some(sorting_key) = &app_state.sorting_by {
-        match sorting_key {
-            ColumnType::PID => {
-                all_threads.sort_by(|a, b| a.pid().cmp(&b.pid()));
-            }
-            ColumnType::NAME => {
-                all_threads.sort_by(|a, b| {
-                    a.name()
-                        .to_string()
-                        .to_lowercase()
-                        .cmp(&b.name().to_string().to_lowercase())
-                });
-            }
-            ColumnType::CPU => {
-                all_threads.sort_by(|a, b| {
-                    b.cpu_usage()
-                        .partial_cmp(&a.cpu_usage())
-                        .unwrap_or(Ordering::Equal)
-                });
-            }
-            ColumnType::RUNTIME => {
-                all_threads.sort_by(|a, b| b.total_runtime().cmp(&a.total_runtime()));
-            }
-            ColumnType::MEMORY => {
-                all_threads.sort_by(|a, b| b.memory().cmp(&a.memory()));
-            }
-            ColumnType::MEMORY_SWAP => {
-                all_threads.sort_by(|a, b| b.total_swap().cmp(&a.total_swap()));
-            }
+    match sorting_key {
+        ColumnType::PID => a.task_pid().cmp(&b.task_pid()),
+        ColumnType::NAME => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        ColumnType::CPU => b
+            .cpu_percent
+            .partial_cmp(&a.cpu_percent)
+            .unwrap_or(Ordering::Equal),
+        ColumnType::RUNTIME => b.runtime.cmp(&a.runtime),
+        ColumnType::MEMORY => b.memory.cmp(&a.memory),
+        ColumnType::MEMORY_SWAP => b.swap.cmp(&a.swap),
+        ColumnType::USER => a.user.cmp(&b.user),
+        ColumnType::STATE => a.state.cmp(&b.state),
+        ColumnType::PPID => a.ppid.cmp(&b.ppid),
+        ColumnType::PRIORITY => a.priority.cmp(&b.priority),
+        ColumnType::NICE => a.nice.cmp(&b.nice),
+        ColumnType::NLWP => b.threads.cmp(&a.threads),
+        ColumnType::VIRT => b.virtual_memory.cmp(&a.virtual_memory),
+        ColumnType::SHR => b.shared_memory.cmp(&a.shared_memory),
+        ColumnType::START => b.start_time.cmp(&a.start_time),
+        ColumnType::TTY => a.tty.cmp(&b.tty),
+        ColumnType::AFFINITY => a.cpus_allowed.cmp(&b.cpus_allowed),
+    }
+}
+
+fn sort_entries(entries: &mut Vec<Entry>, app_state: &AppState) {
+    if let Some(sorting_key) = app_state.sorting_by {
+        entries.sort_by(|a, b| compare_entries(a, b, sorting_key));
+    } else if app_state.tree_view {
+        // keep the tree stable between refreshes
+        entries.sort_by_key(|e| e.process.task_pid());
+    }
+}
+
+/// nests processes under their parents, htop style. siblings are sorted, but
+/// the tree itself is kept. threads hang off the process they belong to
+fn build_tree<'p>(mut entries: Vec<Entry<'p>>, app_state: &AppState) -> Vec<Entry<'p>> {
+    let process_pids: HashSet<i32> = entries
+        .iter()
+        .filter(|e| e.id.tid.is_none())
+        .map(|e| e.id.pid)
+        .collect();
+    let mut children: HashMap<i32, Vec<Entry>> = HashMap::new();
+    let mut roots = Vec::new();
+    for entry in entries.drain(..) {
+        let parent = match entry.id.tid {
+            // the main thread is the process itself
+            Some(tid) if tid == entry.id.pid => continue,
+            Some(_) => Some(entry.id.pid),
+            None => entry.process.ppid.filter(|p| process_pids.contains(p)),
+        };
+        match parent {
+            Some(parent) => children.entry(parent).or_default().push(entry),
+            None => roots.push(entry),
         }
     }
+    for siblings in children.values_mut() {
+        sort_entries(siblings, app_state);
+    }
+    sort_entries(&mut roots, app_state);
+
+    let mut tree = Vec::with_capacity(entries.capacity());
+    let last_root = roots.len().saturating_sub(1);
+    for (i, root) in roots.into_iter().enumerate() {
+        add_subtree(
+            &mut tree,
+            root,
+            &mut children,
+            "",
+            i == last_root,
+            true,
+            app_state,
+        );
+    }
+    tree
+}
+
+fn add_subtree<'p>(
+    tree: &mut Vec<Entry<'p>>,
+    mut entry: Entry<'p>,
+    children: &mut HashMap<i32, Vec<Entry<'p>>>,
+    indent: &str,
+    is_last: bool,
+    is_root: bool,
+    app_state: &AppState,
+) {
+    let kids = match entry.id.tid {
+        Some(_) => Vec::new(),
+        None => children.remove(&entry.id.pid).unwrap_or_default(),
+    };
+    let collapsed = entry.id.tid.is_none() && app_state.collapsed.contains(&entry.id.pid);
+    let marker = match collapsed && !kids.is_empty() {
+        true => "+",
+        false => "─",
+    };
+    entry.tree_prefix = match (is_root, is_last) {
+        (true, _) if marker == "+" => String::from("+ "),
+        (true, _) => String::new(),
+        (false, true) => format!("{}└{} ", indent, marker),
+        (false, false) => format!("{}├{} ", indent, marker),
+    };
+    let child_indent = match (is_root, is_last) {
+        (true, _) => String::from(indent),
+        (false, true) => format!("{}   ", indent),
+        (false, false) => format!("{}│  ", indent),
+    };
+    tree.push(entry);
+    if collapsed {
+        return;
+    }
+    let last_kid = kids.len().saturating_sub(1);
+    for (i, kid) in kids.into_iter().enumerate() {
+        add_subtree(
+            tree,
+            kid,
+            children,
+            &child_indent,
+            i == last_kid,
+            false,
+            app_state,
+        );
+    }
+}
+
+/// the rows of the process table, built from a snapshot's process list
+pub fn get_process_vec<'a>(
+    processes: &[ProcessRecord],
+    app_state: &AppState,
+) -> Vec<ProcessRow<'a>> {
+    let mut all_threads: Vec<Entry> = processes
+        .iter()
+        // threads are only there if they were shown when the snapshot was taken
+        .filter(|p| app_state.show_threads || p.tid.is_none())
+        .filter(|p| match &app_state.filter {
+            Some(filter) => filter.matches(p),
+            None => true,
+        })
+        .map(|process| Entry {
+            id: RowId {
+                pid: process.pid,
+                tid: process.tid,
+            },
+            process,
+            tree_prefix: String::new(),
+        })
+        .collect();
+    if app_state.tree_view {
+        all_threads = build_tree(all_threads, app_state);
+    } else {
+        sort_entries(&mut all_threads, app_state);
+    }
     let mut vec = Vec::new();
-    for process in all_threads.iter() {
+    for entry in all_threads.iter() {
+        let process = entry.process;
         // println!("[{}] {} {:?}", pid, process.name(), process.cpu_usage());
         let mut row = Vec::with_capacity(app_state.headers.len());
         for colum in &app_state.headers {
-            row.push(match colum {
-                ColumnType::PID => {
-                    Spans::from(Span::styled(process.pid().to_string(), Style::default()))
-                }
+            row.push(match colum.kind {
+                ColumnType::PID => Spans::from(Span::styled(
+                    process.task_pid().to_string(),
+                    Style::default(),
+                )),
                 ColumnType::NAME => {
-                    Spans::from(pretty_cmd(process.name(), process.exe(), process.cmd()))
+                    let mut spans =
+                        pretty_cmd(&process.name, Path::new(&process.exe), &process.cmd);
+                    if !entry.tree_prefix.is_empty() {
+                        spans.insert(
+                            0,
+                            Span::styled(
+                                entry.tree_prefix.clone(),
+                                Style::default().fg(Color::Cyan),
+                            ),
+                        );
+                    }
+                    Spans::from(spans)
                     // Spans::from(Span::styled(process.name().to_string(), Style::default()))
                 }
                 ColumnType::CPU => Spans::from(Span::styled(
-                    format!("{:.2}", process.cpu_usage()),
+                    format!("{:.2}", process.cpu_percent),
                     Style::default(),
                 )),
                 ColumnType::RUNTIME => {
-                    let process_runtime = process.total_runtime();
+                    let process_runtime = process.runtime;
                     let seconds = process_runtime % 60;
                     let minutes = (process_runtime / 60) % 60;
                     let hours = (process_runtime / 60) / 60;
@@ -87,28 +256,156 @@ pub fn get_process_vec<'a>(
                     ))
                 }
                 ColumnType::MEMORY => {
-                    let bytes = process.memory() * 1000;
-                    // TODO: just do this yourself - no need for another library here!!!
-                    Spans::from(Span::styled(
-                        bytefmt::format(bytes).replace("B", ""),
-                        Style::default(),
-                    ))
+                    Spans::from(Span::styled(format_kb(process.memory), Style::default()))
                 }
                 ColumnType::MEMORY_SWAP => {
-                    let bytes = process.total_swap() * 1000;
-                    // TODO: just do this yourself - no need for another library here!!!
-                    Spans::from(Span::styled(
-                        bytefmt::format(bytes).replace("B", ""),
-                        Style::default(),
-                    ))
+                    Spans::from(Span::styled(format_kb(process.swap), Style::default()))
+                }
+                ColumnType::USER => {
+                    Spans::from(Span::styled(process.user.clone(), Style::default()))
+                }
+                ColumnType::STATE => {
+                    let style = match process.state {
+                        'R' => Style::default().fg(Color::Green),
+                        'D' => Style::default().fg(Color::Red),
+                        'Z' => Style::default().fg(Color::Magenta),
+                        _ => Style::default(),
+                    };
+                    Spans::from(Span::styled(process.state.to_string(), style))
+                }
+                ColumnType::PPID => Spans::from(Span::styled(
+                    process.ppid.map_or(String::from("-"), |p| p.to_string()),
+                    Style::default(),
+                )),
+                ColumnType::PRIORITY => {
+                    Spans::from(Span::styled(process.priority.to_string(), Style::default()))
+                }
+                ColumnType::NICE => {
+                    let style = match process.nice {
+                        n if n < 0 => Style::default().fg(Color::Red),
+                        n if n > 0 => Style::default().fg(Color::Green),
+                        _ => Style::default(),
+                    };
+                    Spans::from(Span::styled(process.nice.to_string(), style))
+                }
+                ColumnType::NLWP => {
+                    Spans::from(Span::styled(process.threads.to_string(), Style::default()))
+                }
+                ColumnType::VIRT => Spans::from(Span::styled(
+                    format_kb(process.virtual_memory),
+                    Style::default(),
+                )),
+                ColumnType::SHR => Spans::from(Span::styled(
+                    format_kb(process.shared_memory),
+                    Style::default(),
+                )),
+                ColumnType::START => Spans::from(Span::styled(
+                    format_start_time(process.start_time),
+                    Style::default(),
+                )),
+                ColumnType::TTY => Spans::from(Span::styled(process.tty.clone(), Style::default())),
+                ColumnType::AFFINITY => {
+                    Spans::from(Span::styled(process.cpus_allowed.clone(), Style::default()))
                 }
             });
         }
-        vec.push(row);
+        let search_hit = match &app_state.search {
+            Some(search) => search.matches(process),
+            None => false,
+        };
+        vec.push(ProcessRow {
+            id: entry.id,
+            cells: row,
+            search_hit,
+        });
     }
     vec
 }
 
+/// every process (and thread, if they're shown) in sysinfo's process list,
+/// sorted by pid
+pub fn get_process_records(
+    processes: &HashMap<i32, Process>,
+    options: &SnapshotOptions,
+    user_names: &HashMap<u32, String>,
+) -> Vec<ProcessRecord> {
+    let root = Path::new(LIVE_ROOT);
+    let mut all_threads = get_threads_from_process_map(processes, options.show_threads);
+    all_threads.sort_by_key(|(id, _)| (id.pid, id.tid));
+    all_threads
+        .into_iter()
+        .map(|(id, process)| {
+            let dir = process_dir(root, process.pid());
+            let mut stat = match options.read_proc_stat {
+                true => read_proc_stat(&dir).unwrap_or_default(),
+                false => ProcStat::default(),
+            };
+            if options.read_affinity {
+                if let Some(cpus) = read_cpus_allowed(&dir) {
+                    stat.cpus_allowed = cpus;
+                }
+            }
+            ProcessRecord {
+                pid: id.pid,
+                tid: id.tid,
+                ppid: process.parent(),
+                name: process.name().to_string(),
+                cmd: process.cmd().to_vec(),
+                exe: process.exe().to_string_lossy().to_string(),
+                uid: process.uid,
+                user: user_name(user_names, process.uid),
+                cpu_percent: process.cpu_usage(),
+                memory: process.memory(),
+                virtual_memory: process.virtual_memory(),
+                shared_memory: stat.shared,
+                swap: process.total_swap(),
+                runtime: process.total_runtime(),
+                start_time: process.start_time(),
+                state: stat.state,
+                priority: stat.priority,
+                nice: stat.nice,
+                threads: stat.num_threads,
+                tty: tty_name(stat.tty_nr),
+                cpus_allowed: stat.cpus_allowed,
+            }
+        })
+        .collect()
+}
+
+fn format_kb(kb: u64) -> String {
+    // TODO: just do this yourself - no need for another library here!!!
+    bytefmt::format(kb * 1000).replace("B", "")
+}
+
+/// the uid if there's no name for it
+pub fn user_name(user_names: &HashMap<u32, String>, uid: u32) -> String {
+    match user_names.get(&uid) {
+        Some(name) => name.clone(),
+        None => uid.to_string(),
+    }
+}
+
+/// like ps: HH:MM for processes started in the last day, otherwise MonDD
+fn format_start_time(start_time: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let time = start_time as libc::time_t;
+    // localtime_r fills in tm, and doesn't touch any shared state
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return String::from("?");
+    }
+    if now.saturating_sub(start_time) < 24 * 60 * 60 {
+        format!("{:02}:{:02}", tm.tm_hour, tm.tm_min)
+    } else {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        format!("{}{:02}", MONTHS[tm.tm_mon as usize % 12], tm.tm_mday)
+    }
+}
+
 fn pretty_cmd<'a>(name: &str, exe: &Path, cmd: &[String]) -> Vec<Span<'a>> {
     let green = Style::default().fg(Color::Green);
     let purple = Style::default().fg(Color::LightMagenta);
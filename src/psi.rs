@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// one line of a /proc/pressure file. the averages are percentages of wall time
+/// spent stalled, total is microseconds since boot
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct PressureLine {
+    pub avg10: f32,
+    pub avg60: f32,
+    pub avg300: f32,
+    pub total: u64,
+}
+
+/// "some": at least one task stalled. "full": every non-idle task stalled at once
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct ResourcePressure {
+    pub some: PressureLine,
+    /// older kernels don't report full for cpu
+    pub full: Option<PressureLine>,
+}
+
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct Pressure {
+    pub cpu: ResourcePressure,
+    pub memory: ResourcePressure,
+    pub io: ResourcePressure,
+}
+
+const PRESSURE_DIR: &str = "proc/pressure";
+
+/// None if the kernel was built without PSI or it's turned off (psi=0). `root`
+/// is / for this machine
+pub fn read_pressure(root: &Path) -> Option<Pressure> {
+    let dir = root.join(PRESSURE_DIR);
+    let read = |resource: &str| {
+        fs::read_to_string(dir.join(resource))
+            .ok()
+            .and_then(|contents| parse_pressure(&contents))
+    };
+    Some(Pressure {
+        cpu: read("cpu")?,
+        memory: read("memory")?,
+        io: read("io")?,
+    })
+}
+
+/// some avg10=0.00 avg60=0.00 avg300=0.00 total=0
+/// full avg10=0.00 avg60=0.00 avg300=0.00 total=0
+fn parse_pressure(contents: &str) -> Option<ResourcePressure> {
+    let mut some = None;
+    let mut full = None;
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?;
+        let mut pressure = PressureLine::default();
+        for field in fields {
+            let (key, value) = match field.find('=') {
+                Some(i) => (&field[..i], &field[i + 1..]),
+                None => continue,
+            };
+            match key {
+                "avg10" => pressure.avg10 = value.parse().ok()?,
+                "avg60" => pressure.avg60 = value.parse().ok()?,
+                "avg300" => pressure.avg300 = value.parse().ok()?,
+                "total" => pressure.total = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        match kind {
+            "some" => some = Some(pressure),
+            "full" => full = Some(pressure),
+            _ => {}
+        }
+    }
+    Some(ResourcePressure { some: some?, full })
+}
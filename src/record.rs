@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::snapshot::Snapshot;
+
+/// the start of every recording. bump the number if the frame layout changes
+pub const MAGIC: &[u8] = b"ktop recording 1\n";
+/// time (u64) and length (u32), both little endian
+pub const FRAME_HEADER_LEN: usize = 12;
+
+/// `--record`: writes a frame for every refresh. a frame is the time it was
+/// taken, in milliseconds since the unix epoch, then the length of the rest of
+/// the frame, then the snapshot as deflated json. each frame is compressed on
+/// its own, so a replay can jump straight to any of them
+pub struct Recorder {
+    out: BufWriter<File>,
+    /// the wall clock when the recording started, in ms since the unix epoch
+    start_time: u64,
+    /// frame times are measured from this, so they keep going forward even if
+    /// the wall clock is set back, e.g. by ntp
+    started: Instant,
+}
+
+impl Recorder {
+    /// overwrites `path` if it's already there
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            start_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn write(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        let time = self.start_time + self.started.elapsed().as_millis() as u64;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, snapshot)?;
+        let data = encoder.finish()?;
+        self.out.write_all(&time.to_le_bytes())?;
+        self.out.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.out.write_all(&data)?;
+        // a box that's thrashing might not live to see the next frame
+        self.out.flush()
+    }
+}
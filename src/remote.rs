@@ -0,0 +1,825 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::snapshot::Snapshot;
+use crate::source::{LiveSource, MetricsSource, SnapshotOptions, LIVE_ROOT};
+use crate::zswap::ZswapSource;
+
+/// the first thing an agent sends. bump the number if the messages change
+const MAGIC: &[u8] = b"ktop agent 2\n";
+/// far bigger than any snapshot. a length past this isn't from ktop
+const MAX_MESSAGE_LEN: u32 = 256 * 1024 * 1024;
+/// requests are a few numbers, so the agent doesn't read anything bigger
+const MAX_REQUEST_LEN: u32 = 64 * 1024;
+/// how long either end waits on the other in the middle of a message
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// how long the agent waits for a client's next request before hanging up
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// how long a client has to send its login, all told
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// clients served at once. every one has its own copy of the process list
+const MAX_CLIENTS: usize = 16;
+/// connections that haven't logged in yet. they don't count as clients, so
+/// they can't keep logged in ones out for longer than `LOGIN_TIMEOUT`
+const MAX_LOGINS: usize = 16;
+
+/// sent by the client after the agent's magic
+#[derive(Serialize, Deserialize)]
+struct Login {
+    /// the contents of `--token`, if it was given
+    token: Option<String>,
+}
+
+/// sent by the agent once it's happy with the login, as `Ok(hello)`, or
+/// `Err(why)` before it hangs up
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    hostname: String,
+    /// whether kills, renices and so on are carried out
+    allow_actions: bool,
+}
+
+/// client to agent
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Refresh(SnapshotOptions),
+    Signal {
+        pid: i32,
+        tid: Option<i32>,
+        signal: i32,
+    },
+    Renice {
+        pid: i32,
+        tid: Option<i32>,
+        change: i64,
+    },
+    GetAffinity {
+        pid: i32,
+        tid: Option<i32>,
+        num_cpus: usize,
+    },
+    SetAffinity {
+        pid: i32,
+        tid: Option<i32>,
+        cpus: Vec<bool>,
+    },
+}
+
+/// agent to client, one for every request
+#[derive(Serialize, Deserialize)]
+enum Reply {
+    Snapshot(Box<Snapshot>),
+    Affinity(Vec<bool>),
+    Done,
+    /// what went wrong, for the status line
+    Error(String),
+}
+
+/// a message is its length (u32, little endian), then deflated json - the
+/// same as a frame of a recording, without the time
+fn write_message(out: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    serde_json::to_writer(&mut encoder, message)?;
+    let data = encoder.finish()?;
+    out.write_all(&(data.len() as u32).to_le_bytes())?;
+    out.write_all(&data)?;
+    out.flush()
+}
+
+/// refuses messages longer than `max_len`, before or after inflating them
+fn read_message<T: DeserializeOwned>(input: &mut impl Read, max_len: u32) -> io::Result<T> {
+    let too_big = |len| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} byte message - not from ktop", len),
+        )
+    };
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > max_len {
+        return Err(too_big(len as usize));
+    }
+    let mut data = vec![0; len as usize];
+    input.read_exact(&mut data)?;
+    let mut json = Vec::new();
+    DeflateDecoder::new(data.as_slice())
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut json)?;
+    if json.len() > max_len as usize {
+        return Err(too_big(json.len()));
+    }
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// the secret in a `--token` file, without the newline around it
+pub fn read_token(path: &Path) -> io::Result<String> {
+    let token = fs::read_to_string(path)?.trim().to_string();
+    if token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the token is empty",
+        ));
+    }
+    Ok(token)
+}
+
+/// looks at every byte, so the time it takes doesn't say how much of a guess was right
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// `--serve`: a headless ktop for `ktop --connect`. every client gets its own
+/// view of this machine, refreshed whenever it asks, so cpu usage is worked out
+/// over that client's refresh time. zswap stats are only served if debugfs can
+/// be read by the agent itself
+pub fn serve(
+    addr: &str,
+    allow_actions: bool,
+    token: Option<String>,
+    zswap_dir: Option<PathBuf>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("ktop: serving on {}", listener.local_addr()?);
+    let agent = Arc::new(Agent::new(allow_actions, token));
+    let logins = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("ktop: could not accept a client: {}", e);
+                continue;
+            }
+        };
+        let login = match Slot::take(&logins, MAX_LOGINS) {
+            Some(login) => login,
+            None => {
+                let _ = refuse(&stream, "too many clients logging in, try again later");
+                continue;
+            }
+        };
+        let zswap_source = zswap_dir.clone().map(ZswapSource::Debugfs);
+        let agent = Arc::clone(&agent);
+        thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map_or_else(|_| String::from("?"), |addr| addr.to_string());
+            let make_source = || LiveSource::new(zswap_source);
+            if let Err(e) = handle_client(stream, make_source, &agent, login) {
+                eprintln!("ktop: {}: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// how `serve` was started, shared by every client's thread
+struct Agent {
+    /// whether kills, renices and so on are carried out
+    allow_actions: bool,
+    /// what clients have to log in with
+    token: Option<String>,
+    /// `LOGIN_TIMEOUT`, unless testing
+    login_timeout: Duration,
+    /// how many clients are logged in
+    clients: Arc<AtomicUsize>,
+}
+
+impl Agent {
+    fn new(allow_actions: bool, token: Option<String>) -> Self {
+        Self {
+            allow_actions,
+            token,
+            login_timeout: LOGIN_TIMEOUT,
+            clients: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+/// one of a limited number of places, given back when it's dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// None if all `max` of them are taken
+    fn take(count: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                Some(n + 1).filter(|n| *n <= max)
+            })
+            .ok()?;
+        Some(Self(Arc::clone(count)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// reads from the stream until `deadline`, however many reads that takes,
+/// so a client can't keep a login going by sending a byte at a time
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let too_slow = || io::Error::new(io::ErrorKind::TimedOut, "took too long to log in");
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            return Err(too_slow());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => too_slow(),
+            _ => e,
+        })
+    }
+}
+
+/// turns a client away before it's logged in, without reading anything from it
+fn refuse(stream: &TcpStream, why: &str) -> io::Result<()> {
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut out = BufWriter::new(stream);
+    out.write_all(MAGIC)?;
+    write_message(&mut out, &Err::<Hello, _>(why))
+}
+
+/// answers one client until it hangs up. `login` is held until the client has
+/// logged in, and then it takes one of the agent's `MAX_CLIENTS`. the source
+/// is only made after that, since a live one reads every process
+fn handle_client<S: MetricsSource>(
+    stream: TcpStream,
+    make_source: impl FnOnce() -> S,
+    agent: &Agent,
+    login: Slot,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut out = BufWriter::new(stream.try_clone()?);
+    out.write_all(MAGIC)?;
+    out.flush()?;
+    // unbuffered, so nothing after the login is read before the deadline's lifted
+    let mut deadline = DeadlineReader {
+        stream: &stream,
+        deadline: Instant::now() + agent.login_timeout,
+    };
+    let sent: Login = read_message(&mut deadline, MAX_REQUEST_LEN)?;
+    let refused = match (agent.token.as_deref(), sent.token.as_deref()) {
+        (Some(token), Some(sent)) if same_token(token, sent) => None,
+        (Some(_), Some(_)) => Some("wrong token"),
+        (Some(_), None) => Some("this agent needs --token"),
+        (None, _) => None,
+    };
+    if let Some(why) = refused {
+        // slows down guessing
+        thread::sleep(Duration::from_secs(1));
+        write_message(&mut out, &Err::<Hello, _>(why))?;
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, why));
+    }
+    drop(login);
+    let _slot = match Slot::take(&agent.clients, MAX_CLIENTS) {
+        Some(slot) => slot,
+        None => {
+            write_message(
+                &mut out,
+                &Err::<Hello, _>("too many clients, try again later"),
+            )?;
+            return Ok(());
+        }
+    };
+    let mut source = make_source();
+    let hello = Hello {
+        hostname: hostname(),
+        allow_actions: agent.allow_actions && source.can_change_processes(),
+    };
+    write_message(&mut out, &Ok::<_, &str>(&hello))?;
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut input = BufReader::new(stream);
+    // the processes and threads in the last snapshot sent, the only ones the
+    // client can act on
+    let mut tasks = HashSet::new();
+    loop {
+        let request = match read_message(&mut input, MAX_REQUEST_LEN) {
+            Ok(request) => request,
+            Err(e) => match e.kind() {
+                io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut => return Ok(()),
+                _ => return Err(e),
+            },
+        };
+        if let Err(e) = check_numbers(&request) {
+            write_message(&mut out, &Reply::Error(e))?;
+            continue;
+        }
+        let done = |result: Result<(), String>| match result {
+            Ok(()) => Reply::Done,
+            Err(e) => Reply::Error(e),
+        };
+        let reply = match request {
+            Request::Refresh(options) => match source.refresh(&options) {
+                Ok(snapshot) => {
+                    tasks = snapshot.processes.iter().map(|p| (p.pid, p.tid)).collect();
+                    Reply::Snapshot(Box::new(snapshot))
+                }
+                Err(e) => Reply::Error(e.to_string()),
+            },
+            _ if !hello.allow_actions => Reply::Error(format!(
+                "the agent on {} wasn't started with --allow-actions",
+                hello.hostname
+            )),
+            Request::Signal { pid, tid, signal } if is_task(&tasks, pid, tid) => {
+                done(source.send_signal(pid, tid, signal))
+            }
+            Request::Renice { pid, tid, change } if is_task(&tasks, pid, tid) => {
+                done(source.renice(pid, tid, change))
+            }
+            Request::GetAffinity { pid, tid, num_cpus } if is_task(&tasks, pid, tid) => {
+                match source.get_affinity(pid, tid, num_cpus) {
+                    Ok(cpus) => Reply::Affinity(cpus),
+                    Err(e) => Reply::Error(e),
+                }
+            }
+            Request::SetAffinity { pid, tid, cpus } if is_task(&tasks, pid, tid) => {
+                done(source.set_affinity(pid, tid, &cpus))
+            }
+            _ => Reply::Error(format!(
+                "that isn't one of the processes the agent on {} last sent",
+                hello.hostname
+            )),
+        };
+        write_message(&mut out, &reply)?;
+    }
+}
+
+/// refuses numbers that would overflow or be out of range in the syscalls
+/// behind a request, since they come from the client
+fn check_numbers(request: &Request) -> Result<(), String> {
+    // nice values go from -20 to 19
+    const MAX_NICE_CHANGE: i64 = 39;
+    let max_cpus = libc::CPU_SETSIZE as usize;
+    match request {
+        Request::Renice { change, .. }
+            if !(-MAX_NICE_CHANGE..=MAX_NICE_CHANGE).contains(change) =>
+        {
+            Err(format!("can't move a nice value by {}", change))
+        }
+        Request::GetAffinity { num_cpus, .. } if *num_cpus > max_cpus => {
+            Err(format!("only the first {} cpus can be pinned to", max_cpus))
+        }
+        Request::SetAffinity { cpus, .. } if cpus.len() > max_cpus => {
+            Err(format!("only the first {} cpus can be pinned to", max_cpus))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// whether the process, or its thread `tid`, is in `tasks`. kill(2) and the
+/// rest treat pids of 0 and below as groups of processes, so they never are
+fn is_task(tasks: &HashSet<(i32, Option<i32>)>, pid: i32, tid: Option<i32>) -> bool {
+    pid > 0 && tid.map_or(true, |tid| tid > 0) && tasks.contains(&(pid, tid))
+}
+
+fn hostname() -> String {
+    fs::read_to_string(Path::new(LIVE_ROOT).join("proc/sys/kernel/hostname"))
+        .map_or_else(|_| String::from("?"), |name| name.trim().to_string())
+}
+
+struct Connection {
+    input: BufReader<TcpStream>,
+    out: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn open(addr: &str, token: Option<&str>) -> io::Result<(Self, Hello)> {
+        let mut last_error = None;
+        let mut stream = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let stream = match (stream, last_error) {
+            (Some(stream), _) => stream,
+            (None, Some(e)) => return Err(e),
+            (None, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no address to connect to",
+                ))
+            }
+        };
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut input = BufReader::new(stream.try_clone()?);
+        let mut magic = vec![0; MAGIC.len()];
+        if input.read_exact(&mut magic).is_err() || magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a ktop agent, or a different version of one",
+            ));
+        }
+        let mut out = BufWriter::new(stream);
+        let login = Login {
+            token: token.map(String::from),
+        };
+        write_message(&mut out, &login)?;
+        let hello: Result<Hello, String> = read_message(&mut input, MAX_MESSAGE_LEN)?;
+        match hello {
+            Ok(hello) => Ok((Self { input, out }, hello)),
+            Err(why) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("the agent refused: {}", why),
+            )),
+        }
+    }
+}
+
+/// `--connect`: the meters and processes of a machine running `ktop --serve`
+pub struct RemoteSource {
+    /// HOST:PORT, as given
+    addr: String,
+    token: Option<String>,
+    hello: Hello,
+    /// None once the agent has gone away. the next request tries to connect again
+    connection: Option<Connection>,
+}
+
+impl RemoteSource {
+    pub fn connect(addr: &str, token: Option<String>) -> io::Result<Self> {
+        let (connection, hello) = Connection::open(addr, token.as_deref())?;
+        Ok(Self {
+            addr: addr.to_string(),
+            token,
+            hello,
+            connection: Some(connection),
+        })
+    }
+
+    fn request(&mut self, request: &Request) -> io::Result<Reply> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => {
+                let (connection, hello) = Connection::open(&self.addr, self.token.as_deref())?;
+                self.hello = hello;
+                connection
+            }
+        };
+        let reply = write_message(&mut connection.out, request)
+            .and_then(|_| read_message(&mut connection.input, MAX_MESSAGE_LEN))
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => io::Error::new(e.kind(), "the agent hung up"),
+                _ => e,
+            });
+        // a connection that failed halfway through a message can't be trusted
+        if reply.is_ok() {
+            self.connection = Some(connection);
+        }
+        reply
+    }
+
+    fn act(&mut self, request: Request) -> Result<Reply, String> {
+        match self.request(&request) {
+            Ok(Reply::Error(e)) => Err(e),
+            Ok(reply) => Ok(reply),
+            Err(e) => Err(format!("{}: {}", self.addr, e)),
+        }
+    }
+}
+
+fn unexpected_reply() -> String {
+    String::from("the agent sent back something unexpected")
+}
+
+impl MetricsSource for RemoteSource {
+    fn refresh(&mut self, options: &SnapshotOptions) -> io::Result<Snapshot> {
+        let reply = self.request(&Request::Refresh(*options)).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Could not refresh from {}: {}", self.addr, e),
+            )
+        })?;
+        match reply {
+            Reply::Snapshot(snapshot) => Ok(*snapshot),
+            Reply::Error(e) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{}: {}", self.hello.hostname, e),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                unexpected_reply(),
+            )),
+        }
+    }
+
+    fn name(&self) -> Option<String> {
+        Some(format!("{} ({})", self.hello.hostname, self.addr))
+    }
+
+    fn can_change_processes(&self) -> bool {
+        self.hello.allow_actions
+    }
+
+    fn send_signal(&mut self, pid: i32, tid: Option<i32>, signal: i32) -> Result<(), String> {
+        self.act(Request::Signal { pid, tid, signal }).map(|_| ())
+    }
+
+    fn renice(&mut self, pid: i32, tid: Option<i32>, change: i64) -> Result<(), String> {
+        self.act(Request::Renice { pid, tid, change }).map(|_| ())
+    }
+
+    fn get_affinity(
+        &mut self,
+        pid: i32,
+        tid: Option<i32>,
+        num_cpus: usize,
+    ) -> Result<Vec<bool>, String> {
+        match self.act(Request::GetAffinity { pid, tid, num_cpus })? {
+            Reply::Affinity(cpus) => Ok(cpus),
+            _ => Err(unexpected_reply()),
+        }
+    }
+
+    fn set_affinity(&mut self, pid: i32, tid: Option<i32>, cpus: &[bool]) -> Result<(), String> {
+        self.act(Request::SetAffinity {
+            pid,
+            tid,
+            cpus: cpus.to_vec(),
+        })
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    use super::*;
+    use crate::snapshot::ProcessRecord;
+
+    const TOKEN: &str = "hunter2";
+
+    fn task(pid: i32, tid: Option<i32>) -> ProcessRecord {
+        ProcessRecord {
+            pid,
+            tid,
+            ppid: None,
+            name: String::from("cargo"),
+            cmd: vec![String::from("cargo")],
+            exe: String::from("/usr/bin/cargo"),
+            uid: 1000,
+            user: String::from("kurt"),
+            cpu_percent: 0.0,
+            memory: 0,
+            virtual_memory: 0,
+            shared_memory: 0,
+            swap: 0,
+            runtime: 0,
+            start_time: 0,
+            state: 'S',
+            priority: 20,
+            nice: 0,
+            threads: 2,
+            tty: String::from("?"),
+            cpus_allowed: String::from("0-3"),
+        }
+    }
+
+    /// process 421 and its thread 425 at a set time, and the signals it's been
+    /// asked to send
+    struct FakeSource {
+        signals: Sender<(i32, Option<i32>, i32)>,
+    }
+
+    impl MetricsSource for FakeSource {
+        fn refresh(&mut self, _options: &SnapshotOptions) -> io::Result<Snapshot> {
+            Ok(Snapshot {
+                time: 42,
+                processes: vec![task(421, None), task(421, Some(425))],
+                ..Snapshot::default()
+            })
+        }
+
+        fn can_change_processes(&self) -> bool {
+            true
+        }
+
+        fn send_signal(&mut self, pid: i32, tid: Option<i32>, signal: i32) -> Result<(), String> {
+            self.signals.send((pid, tid, signal)).unwrap();
+            Ok(())
+        }
+    }
+
+    /// an agent on localhost that wants `TOKEN`, for one client
+    fn agent(allow_actions: bool) -> (String, Receiver<(i32, Option<i32>, i32)>) {
+        agent_with(Agent::new(allow_actions, Some(String::from(TOKEN))))
+    }
+
+    fn agent_with(agent: Agent) -> (String, Receiver<(i32, Option<i32>, i32)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (signals, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let make_source = || FakeSource { signals };
+            let login = Slot::take(&Arc::new(AtomicUsize::new(0)), 1).unwrap();
+            let _ = handle_client(stream, make_source, &agent, login);
+        });
+        (addr, received)
+    }
+
+    fn connect(addr: &str) -> RemoteSource {
+        RemoteSource::connect(addr, Some(String::from(TOKEN))).unwrap()
+    }
+
+    const OPTIONS: SnapshotOptions = SnapshotOptions {
+        show_threads: true,
+        read_proc_stat: false,
+        read_affinity: false,
+    };
+
+    #[test]
+    fn refresh() {
+        let (addr, _) = agent(false);
+        let mut remote = connect(&addr);
+        assert_eq!(remote.refresh(&OPTIONS).unwrap().time, 42);
+        assert_eq!(remote.refresh(&OPTIONS).unwrap().time, 42);
+    }
+
+    #[test]
+    fn actions_are_refused_unless_allowed() {
+        let (addr, received) = agent(false);
+        let mut remote = connect(&addr);
+        assert!(!remote.can_change_processes());
+        assert!(remote.send_signal(421, None, libc::SIGTERM).is_err());
+        assert!(received.try_recv().is_err());
+        // still connected
+        assert_eq!(remote.refresh(&OPTIONS).unwrap().time, 42);
+    }
+
+    #[test]
+    fn actions_are_forwarded() {
+        let (addr, received) = agent(true);
+        let mut remote = connect(&addr);
+        assert!(remote.can_change_processes());
+        remote.refresh(&OPTIONS).unwrap();
+        remote.send_signal(421, Some(425), libc::SIGTERM).unwrap();
+        assert_eq!(received.recv().unwrap(), (421, Some(425), libc::SIGTERM));
+        // not implemented by the fake source, so the agent's error comes back
+        let e = remote.renice(421, Some(425), 1).unwrap_err();
+        assert!(!e.contains("last sent"), "{}", e);
+    }
+
+    #[test]
+    fn only_processes_in_the_last_refresh() {
+        let (addr, received) = agent(true);
+        let mut remote = connect(&addr);
+        // nothing's been sent yet
+        assert!(remote.send_signal(421, None, libc::SIGTERM).is_err());
+        remote.refresh(&OPTIONS).unwrap();
+        // -1 is every process the agent can signal, 0 its process group
+        for pid in [-1, 0, 1, 422] {
+            let e = remote.send_signal(pid, None, libc::SIGTERM).unwrap_err();
+            assert!(e.contains("last sent"), "{}", e);
+        }
+        assert!(remote.send_signal(421, Some(0), libc::SIGTERM).is_err());
+        assert!(remote.send_signal(421, Some(426), libc::SIGTERM).is_err());
+        assert!(remote.send_signal(425, None, libc::SIGTERM).is_err());
+        assert!(remote
+            .set_affinity(-1, None, &[true])
+            .unwrap_err()
+            .contains("last sent"));
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn numbers_out_of_range() {
+        let (addr, _) = agent(true);
+        let mut remote = connect(&addr);
+        remote.refresh(&OPTIONS).unwrap();
+        for change in [i64::MAX, i64::MIN, 40, -40] {
+            let e = remote.renice(421, None, change).unwrap_err();
+            assert!(e.contains("nice value"), "{}", e);
+        }
+        let e = remote.get_affinity(421, None, usize::MAX).unwrap_err();
+        assert!(e.contains("cpus"), "{}", e);
+        let e = remote.set_affinity(421, None, &[true; 1025]).unwrap_err();
+        assert!(e.contains("cpus"), "{}", e);
+        // still connected
+        assert_eq!(remote.refresh(&OPTIONS).unwrap().time, 42);
+    }
+
+    #[test]
+    fn wrong_token() {
+        for token in [None, Some(String::from("hunter3"))] {
+            let (addr, _) = agent(true);
+            let e = RemoteSource::connect(&addr, token).err().unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    fn too_many_clients() {
+        let agent = Agent::new(false, Some(String::from(TOKEN)));
+        agent.clients.store(MAX_CLIENTS, Ordering::SeqCst);
+        let (addr, _) = agent_with(agent);
+        let e = RemoteSource::connect(&addr, Some(String::from(TOKEN)))
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let slots: Vec<Slot> = (0..2).filter_map(|_| Slot::take(&count, 2)).collect();
+        assert!(Slot::take(&count, 2).is_none());
+        drop(slots);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn slow_logins() {
+        let agent = Agent {
+            login_timeout: Duration::from_millis(300),
+            ..Agent::new(false, Some(String::from(TOKEN)))
+        };
+        let (addr, _) = agent_with(agent);
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let started = Instant::now();
+        // half of the length, then nothing
+        stream.write_all(&[1, 0]).unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, MAGIC);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn oversized_requests() {
+        let (addr, _) = agent(true);
+        let login = |mut stream: &TcpStream| {
+            let mut magic = vec![0; MAGIC.len()];
+            stream.read_exact(&mut magic).unwrap();
+            let login = Login {
+                token: Some(String::from(TOKEN)),
+            };
+            write_message(&mut stream, &login).unwrap();
+            let hello: Result<Hello, String> = read_message(&mut stream, MAX_MESSAGE_LEN).unwrap();
+            assert!(hello.is_ok());
+        };
+        let hung_up = |mut stream: &TcpStream| {
+            let mut rest = Vec::new();
+            stream.read_to_end(&mut rest).unwrap();
+            assert!(rest.is_empty());
+        };
+        let stream = TcpStream::connect(&addr).unwrap();
+        login(&stream);
+        (&stream)
+            .write_all(&(MAX_REQUEST_LEN + 1).to_le_bytes())
+            .unwrap();
+        hung_up(&stream);
+
+        // small on the wire, but far too big once inflated
+        let (addr, _) = agent(true);
+        let stream = TcpStream::connect(&addr).unwrap();
+        login(&stream);
+        let padding = " ".repeat(MAX_REQUEST_LEN as usize);
+        write_message(&mut &stream, &format!("{}\"Refresh\"", padding)).unwrap();
+        hung_up(&stream);
+    }
+
+    #[test]
+    fn not_an_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        });
+        let e = RemoteSource::connect(&addr, None).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
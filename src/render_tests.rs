@@ -0,0 +1,356 @@
+//! renders the meters and the process table into a `TestBackend` and compares
+//! the text on screen with the golden files in src/golden. colors aren't
+//! compared. after changing how something looks, run the tests with
+//! `KTOP_UPDATE_GOLDEN=1` to write the new renders, and check the diff
+//!
+//! byte sizes are kept under 1 KiB, so the goldens don't depend on how bytefmt
+//! rounds them
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use tui::{backend::TestBackend, Frame, Terminal};
+
+use crate::columns::parse_column_list;
+use crate::config::AppConfig;
+use crate::cpu_stat::CpuBreakdown;
+use crate::fixture::FixtureSource;
+use crate::meminfo::MemoryBreakdown;
+use crate::meter_widget::MeterWidget;
+use crate::psi::{Pressure, PressureLine, ResourcePressure};
+use crate::snapshot::{ProcessRecord, Snapshot, SystemRecord};
+use crate::source::{MetricsSource, SnapshotOptions};
+use crate::util::StatefulTable;
+use crate::{draw_process_table, refresh_sources, update_table, AppState};
+
+/// the screen as text, one line per row, without trailing spaces
+fn render<F>(width: u16, height: u16, draw: F) -> String
+where
+    F: FnOnce(&mut Frame<TestBackend>),
+{
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(draw).unwrap();
+    let buffer = terminal.backend().buffer();
+    let mut text = String::new();
+    for y in 0..height {
+        let line: String = (0..width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+fn assert_golden(name: &str, rendered: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src", "golden", name]
+        .iter()
+        .collect();
+    if env::var_os("KTOP_UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, rendered).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "could not read {}: {} - run with KTOP_UPDATE_GOLDEN=1 to write it",
+            path.display(),
+            e
+        )
+    });
+    assert_eq!(
+        golden, rendered,
+        "{} doesn't match - if the change is intended, run with KTOP_UPDATE_GOLDEN=1",
+        name
+    );
+}
+
+fn render_meter(name: &str, width: u16, height: u16, meter: MeterWidget) {
+    let rendered = render(width, height, |f| f.render_widget(meter, f.size()));
+    assert_golden(name, &rendered);
+}
+
+fn pressure_line(avg10: f32) -> PressureLine {
+    PressureLine {
+        avg10,
+        avg60: avg10 / 2.0,
+        avg300: avg10 / 4.0,
+        total: 90_000_000,
+    }
+}
+
+#[test]
+fn meter_empty() {
+    let meter = MeterWidget {
+        per_cpu: vec![0.0; 4],
+        ..MeterWidget::default()
+    };
+    render_meter("meter_empty_80x6.txt", 80, 6, meter);
+}
+
+#[test]
+fn meter_full() {
+    let meter = MeterWidget {
+        cpu_percent: 1.0,
+        cpu_system_percent: 1.0,
+        memory_percent: 1.0,
+        swap_percent: 1.0,
+        total_swap: 1,
+        per_cpu: vec![1.0; 4],
+        ..MeterWidget::default()
+    };
+    render_meter("meter_full_80x6.txt", 80, 6, meter);
+}
+
+#[test]
+fn meter_breakdown_and_pressure() {
+    let core = CpuBreakdown {
+        user: 0.25,
+        system: 0.1,
+        iowait: 0.05,
+        ..CpuBreakdown::default()
+    };
+    let meter = MeterWidget {
+        cpu_percent: 0.4,
+        cpu_system_percent: 0.1,
+        memory_percent: 0.5,
+        swap_percent: 0.25,
+        total_swap: 1,
+        per_cpu: vec![0.4; 8],
+        per_cpu_breakdown: Some(vec![core; 8]),
+        cpu_breakdown: Some(core),
+        memory_breakdown: Some(MemoryBreakdown {
+            used: 0.5,
+            buffers: 0.05,
+            shared: 0.05,
+            cache: 0.2,
+        }),
+        pressure: Some(Pressure {
+            cpu: ResourcePressure {
+                some: pressure_line(0.0),
+                full: None,
+            },
+            memory: ResourcePressure {
+                some: pressure_line(12.5),
+                full: Some(pressure_line(5.0)),
+            },
+            io: ResourcePressure {
+                some: pressure_line(100.0),
+                full: Some(pressure_line(60.0)),
+            },
+        }),
+        ..MeterWidget::default()
+    };
+    render_meter("meter_breakdown_120x8.txt", 120, 8, meter);
+}
+
+#[test]
+fn meter_compact_cores() {
+    let meter = MeterWidget {
+        cpu_percent: 0.5,
+        per_cpu: (0..16).map(|i| i as f32 / 15.0).collect(),
+        ..MeterWidget::default()
+    };
+    render_meter("meter_compact_60x6.txt", 60, 6, meter);
+}
+
+#[test]
+fn meter_height() {
+    let cores = |count: usize| MeterWidget {
+        per_cpu: vec![0.5; count],
+        ..MeterWidget::default()
+    };
+    // 6 columns of labelled bars fit in 80
+    assert_eq!(cores(8).height(80), 4 + 2);
+    assert_eq!(cores(0).height(80), 4);
+    // one character per cpu
+    assert_eq!(cores(128).height(80), 4 + 2);
+    assert_eq!(cores(8).height(0), 4 + 8);
+    let meter = MeterWidget {
+        pressure: Some(Pressure {
+            cpu: ResourcePressure {
+                some: pressure_line(0.0),
+                full: None,
+            },
+            memory: ResourcePressure {
+                some: pressure_line(0.0),
+                full: Some(pressure_line(0.0)),
+            },
+            io: ResourcePressure {
+                some: pressure_line(0.0),
+                full: Some(pressure_line(0.0)),
+            },
+        }),
+        ..cores(2)
+    };
+    // the pressure panel is taller than the meters next to it
+    assert_eq!(meter.height(120), 6);
+}
+
+#[test]
+fn meter_no_swap() {
+    let system = SystemRecord {
+        memory_total: 8 * 1024 * 1024,
+        per_cpu_percent: vec![50.0; 4],
+        ..SystemRecord::default()
+    };
+    render_meter(
+        "meter_no_swap_80x6.txt",
+        80,
+        6,
+        MeterWidget::new(&system, false),
+    );
+}
+
+#[test]
+fn meter_narrow() {
+    for width in [0, 1, 8, 17, 40] {
+        render_meter(
+            &format!("meter_narrow_{}x6.txt", width),
+            width,
+            6,
+            MeterWidget::default(),
+        );
+    }
+}
+
+/// full bars have the longest labels, so they're the first to not fit
+#[test]
+fn meter_narrow_full() {
+    let meter = || MeterWidget {
+        cpu_percent: 1.0,
+        cpu_system_percent: 1.0,
+        memory_percent: 1.0,
+        swap_percent: 1.0,
+        total_swap: 1,
+        per_cpu: vec![1.0; 4],
+        ..MeterWidget::default()
+    };
+    for (width, height) in [(1, 6), (8, 6), (17, 6), (24, 6), (40, 6), (80, 1), (80, 5)] {
+        render_meter(
+            &format!("meter_narrow_full_{}x{}.txt", width, height),
+            width,
+            height,
+            meter(),
+        );
+    }
+}
+
+/// the table is filled in by hand, nothing is refreshed
+struct NoSource;
+
+impl MetricsSource for NoSource {
+    fn refresh(&mut self, _options: &SnapshotOptions) -> io::Result<Snapshot> {
+        Ok(Snapshot::default())
+    }
+}
+
+fn process(pid: i32, tid: Option<i32>, ppid: i32, cmd: &str, cpu_percent: f32) -> ProcessRecord {
+    let cmd: Vec<String> = cmd.split(' ').map(String::from).collect();
+    ProcessRecord {
+        pid,
+        tid,
+        ppid: Some(ppid).filter(|p| *p != 0),
+        name: cmd[0].rsplit('/').next().unwrap_or_default().to_string(),
+        exe: cmd[0].clone(),
+        cmd,
+        uid: 1000,
+        user: String::from("kurt"),
+        cpu_percent,
+        memory: 0,
+        virtual_memory: 0,
+        shared_memory: 0,
+        swap: 0,
+        runtime: pid as u64 * 61,
+        start_time: 0,
+        state: 'S',
+        priority: 20,
+        nice: 0,
+        threads: 1,
+        tty: String::from("?"),
+        cpus_allowed: String::from("0-3"),
+    }
+}
+
+fn table_state() -> AppState {
+    let mut app_state = AppState::new(&AppConfig::default(), Box::new(NoSource));
+    app_state.snapshot.processes = vec![
+        process(1, None, 0, "/sbin/init splash", 0.0),
+        process(420, None, 1, "/usr/bin/bash", 1.5),
+        process(421, None, 420, "/usr/bin/cargo test", 87.25),
+        process(421, Some(425), 421, "/usr/bin/cargo test", 12.0),
+        process(600, None, 1, "/usr/lib/systemd/systemd-journald", 0.25),
+    ];
+    app_state
+}
+
+fn render_table(name: &str, width: u16, height: u16, app_state: &mut AppState) {
+    let mut table = StatefulTable::new(vec![]);
+    update_table(&mut table, app_state);
+    table.state.select(Some(1));
+    let rendered = render(width, height, |f| {
+        draw_process_table(f, f.size(), &mut table, app_state)
+    });
+    assert_golden(name, &rendered);
+}
+
+#[test]
+fn table_sizes() {
+    let mut app_state = table_state();
+    for (width, height) in [(80, 10), (120, 6)] {
+        render_table(
+            &format!("table_{}x{}.txt", width, height),
+            width,
+            height,
+            &mut app_state,
+        );
+    }
+}
+
+#[test]
+fn table_narrow() {
+    let mut app_state = table_state();
+    for width in [0, 2, 10, 40] {
+        render_table(
+            &format!("table_{}x10.txt", width),
+            width,
+            10,
+            &mut app_state,
+        );
+    }
+}
+
+#[test]
+fn table_tree() {
+    let mut app_state = table_state();
+    app_state.tree_view = true;
+    app_state.show_threads = false;
+    render_table("table_tree_80x8.txt", 80, 8, &mut app_state);
+}
+
+#[test]
+fn table_status_message() {
+    let mut app_state = table_state();
+    app_state.status_message = Some(String::from("Could not kill 421: Operation not permitted"));
+    render_table("table_status_60x8.txt", 60, 8, &mut app_state);
+}
+
+#[test]
+fn table_from_fixture() {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src", "fixtures", "small"]
+        .iter()
+        .collect();
+    // no byte sizes, see the top of the file
+    let config = AppConfig {
+        columns: parse_column_list("pid,user,s,ni,nlwp,tty,cpu,time,cpus,name").unwrap(),
+        ..AppConfig::default()
+    };
+    let source = FixtureSource::open(&dir).unwrap();
+    let mut app_state = AppState::new(&config, Box::new(source));
+    // the second capture, so the cpu column has something to compare against
+    refresh_sources(&mut app_state).unwrap();
+    refresh_sources(&mut app_state).unwrap();
+    render_table("table_fixture_100x10.txt", 100, 10, &mut app_state);
+}
@@ -0,0 +1,233 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Instant;
+
+use flate2::read::DeflateDecoder;
+
+use crate::record::{FRAME_HEADER_LEN, MAGIC};
+use crate::snapshot::Snapshot;
+use crate::util::format_clock_time;
+
+/// how far [ and ] jump, in milliseconds
+pub const SEEK_STEP: i64 = 60 * 1000;
+const MIN_SPEED: f64 = 1.0 / 16.0;
+const MAX_SPEED: f64 = 256.0;
+
+/// where a frame is in the file
+struct FrameIndex {
+    /// milliseconds since the unix epoch
+    time: u64,
+    /// start of the compressed snapshot, after the frame header
+    offset: u64,
+    len: u32,
+}
+
+/// `--replay`: plays a recording back at the pace it was recorded, or faster,
+/// or slower. only the frame index is kept in memory; snapshots are read from
+/// the file as they're shown
+pub struct Replay {
+    file: File,
+    frames: Vec<FrameIndex>,
+    position: usize,
+    /// the recorded time being shown, in ms. moves at `speed` times real time
+    /// while playing, and the frame shown is the last one taken before it
+    clock: f64,
+    speed: f64,
+    paused: bool,
+    last_tick: Instant,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = vec![0; MAGIC.len()];
+        if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a ktop recording",
+            ));
+        }
+        let file_len = reader.get_ref().metadata()?.len();
+        let mut offset = MAGIC.len() as u64;
+        let mut frames = Vec::new();
+        let mut header = [0; FRAME_HEADER_LEN];
+        // a recording that was cut off mid-frame just ends at the last whole one
+        while reader.read_exact(&mut header).is_ok() {
+            let time = u64::from_le_bytes(header[..8].try_into().unwrap());
+            // seeking needs every frame to be later than the one before it, so a
+            // time that went backwards is moved up to just after that frame
+            let time = match frames.last() {
+                Some(FrameIndex { time: previous, .. }) => time.max(previous + 1),
+                None => time,
+            };
+            let len = u32::from_le_bytes(header[8..].try_into().unwrap());
+            offset += FRAME_HEADER_LEN as u64;
+            if offset + len as u64 > file_len {
+                break;
+            }
+            frames.push(FrameIndex { time, offset, len });
+            reader.seek_relative(len as i64)?;
+            offset += len as u64;
+        }
+        let first = match frames.first() {
+            Some(frame) => frame.time as f64,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the recording is empty",
+                ))
+            }
+        };
+        Ok(Self {
+            file: reader.into_inner(),
+            frames,
+            position: 0,
+            clock: first,
+            speed: 1.0,
+            paused: false,
+            last_tick: Instant::now(),
+        })
+    }
+
+    /// reads the frame being shown
+    pub fn load(&mut self) -> io::Result<Snapshot> {
+        let frame = &self.frames[self.position];
+        let mut data = vec![0; frame.len as usize];
+        self.file.seek(SeekFrom::Start(frame.offset))?;
+        self.file.read_exact(&mut data)?;
+        Ok(serde_json::from_reader(DeflateDecoder::new(
+            data.as_slice(),
+        ))?)
+    }
+
+    /// average seconds between frames, for the graphs
+    pub fn interval(&self) -> u64 {
+        let first = self.frames[0].time;
+        let last = self.frames[self.frames.len() - 1].time;
+        let gaps = (self.frames.len() as u64 - 1).max(1);
+        (last.saturating_sub(first) / gaps / 1000).max(1)
+    }
+
+    /// moves the clock along while playing. true if there's a new frame to show
+    pub fn tick(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_tick).as_secs_f64() * 1000.0;
+        self.last_tick = now;
+        if self.paused {
+            return false;
+        }
+        let moved = self.set_clock(self.clock + elapsed * self.speed);
+        // stop at the end instead of sitting on the last frame "playing"
+        if self.position == self.frames.len() - 1 {
+            self.paused = true;
+        }
+        moved
+    }
+
+    /// true if the replay went back to the start
+    pub fn toggle_pause(&mut self) -> bool {
+        self.paused = !self.paused;
+        self.last_tick = Instant::now();
+        // playing from the end starts over
+        match !self.paused && self.position == self.frames.len() - 1 {
+            true => self.set_clock(self.frames[0].time as f64),
+            false => false,
+        }
+    }
+
+    /// one frame forward or back. pauses, so the frame can be looked at
+    pub fn step(&mut self, forward: bool) -> bool {
+        self.paused = true;
+        let position = match forward {
+            true => (self.position + 1).min(self.frames.len() - 1),
+            false => self.position.saturating_sub(1),
+        };
+        self.set_clock(self.frames[position].time as f64)
+    }
+
+    /// moves the clock by `ms`, which can be negative
+    pub fn seek(&mut self, ms: i64) -> bool {
+        self.set_clock(self.clock + ms as f64)
+    }
+
+    pub fn seek_to_start(&mut self) -> bool {
+        self.set_clock(f64::MIN)
+    }
+
+    pub fn seek_to_end(&mut self) -> bool {
+        self.set_clock(f64::MAX)
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SPEED);
+    }
+
+    /// for the title of the process table, like "replay 03:12:44 (40/3600) x4 paused"
+    pub fn status(&self) -> String {
+        let speed = match self.speed {
+            s if s < 1.0 => format!("x1/{}", (1.0 / s).round()),
+            s => format!("x{}", s),
+        };
+        let mut status = format!(
+            "replay {} ({}/{}) {}",
+            format_clock_time(self.frames[self.position].time / 1000),
+            self.position + 1,
+            self.frames.len(),
+            speed
+        );
+        if self.paused {
+            status.push_str(" paused");
+        }
+        status
+    }
+
+    /// shows the last frame taken at or before `clock`. true if that's a
+    /// different frame than before
+    fn set_clock(&mut self, clock: f64) -> bool {
+        let first = self.frames[0].time as f64;
+        let last = self.frames[self.frames.len() - 1].time as f64;
+        self.clock = clock.max(first).min(last);
+        let clock = self.clock;
+        let position = self
+            .frames
+            .partition_point(|f| f.time as f64 <= clock)
+            .saturating_sub(1);
+        let moved = position != self.position;
+        self.position = position;
+        moved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn times_going_backwards() {
+        let path = env::temp_dir().join(format!("ktop-replay-test-{}", std::process::id()));
+        let mut recording = MAGIC.to_vec();
+        // the clock was set back an hour between the second and third frames
+        for time in [10_000_000u64, 10_005_000, 6_410_000, 6_415_000] {
+            recording.extend_from_slice(&time.to_le_bytes());
+            recording.extend_from_slice(&1u32.to_le_bytes());
+            recording.push(0);
+        }
+        fs::write(&path, recording).unwrap();
+        let mut replay = Replay::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replay.interval(), 1);
+        replay.seek_to_end();
+        assert_eq!(replay.position, 3);
+        replay.step(false);
+        assert_eq!(replay.position, 2);
+    }
+}
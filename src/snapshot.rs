@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cpu_stat::CpuBreakdown;
+use crate::meminfo::MemoryBreakdown;
+use crate::psi::Pressure;
+use crate::vmstat::VmStatRates;
+use crate::zram::ZramDevice;
+use crate::zswap::{ZswapParameters, ZswapRates, ZswapStats};
+
+/// everything ktop knows at one refresh. the meters and the process table are
+/// drawn from this, and it's what --format json/csv and --record write out
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// seconds since the unix epoch
+    pub time: u64,
+    pub system: SystemRecord,
+    pub processes: Vec<ProcessRecord>,
+}
+
+/// what the meters show. memory sizes are in KB, percentages 0 to 100
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SystemRecord {
+    pub uptime: u64,
+    pub load_average: [f64; 3],
+    pub cpu_percent: f32,
+    pub cpu_system_percent: f32,
+    /// fractions, 0 to 1
+    pub cpu: CpuBreakdown,
+    pub per_cpu_percent: Vec<f32>,
+    /// like `cpu`, for each core
+    pub cores: Vec<CpuBreakdown>,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub memory: Option<MemoryBreakdown>,
+    pub swap_used: u64,
+    pub swap_total: u64,
+    pub vmstat: VmStatRates,
+    pub pressure: Option<Pressure>,
+    pub zswap: Option<ZswapStats>,
+    pub zswap_rates: Option<ZswapRates>,
+    pub zswap_parameters: Option<ZswapParameters>,
+    /// where the zswap stats come from, like "debugfs at /sys/kernel/debug"
+    pub zswap_source: Option<String>,
+    /// why the zswap stats couldn't be read
+    pub zswap_error: Option<String>,
+    pub zram: Vec<ZramDevice>,
+}
+
+/// one process or thread. memory sizes are in KB
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProcessRecord {
+    pub pid: i32,
+    /// set for threads, which keep the pid of their process
+    pub tid: Option<i32>,
+    pub ppid: Option<i32>,
+    pub name: String,
+    pub cmd: Vec<String>,
+    pub exe: String,
+    pub uid: u32,
+    pub user: String,
+    pub cpu_percent: f32,
+    pub memory: u64,
+    pub virtual_memory: u64,
+    pub shared_memory: u64,
+    pub swap: u64,
+    /// seconds of cpu time
+    pub runtime: u64,
+    /// seconds since the unix epoch
+    pub start_time: u64,
+    pub state: char,
+    pub priority: i64,
+    pub nice: i64,
+    pub threads: i64,
+    pub tty: String,
+    pub cpus_allowed: String,
+}
+
+impl ProcessRecord {
+    /// the tid for threads, the pid otherwise. what /proc and kill(2) take
+    pub fn task_pid(&self) -> i32 {
+        self.tid.unwrap_or(self.pid)
+    }
+}
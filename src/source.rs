@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{ProcessorExt, System, SystemExt};
+
+use crate::columns::ColumnType;
+use crate::cpu_stat::CpuStat;
+use crate::snapshot::{Snapshot, SystemRecord};
+use crate::vmstat::vmstat_info;
+use crate::zswap::{ZswapMonitor, ZswapSource};
+use crate::{meminfo, proc_stat, process_actions, processes, psi, zram, AppState};
+
+/// where this machine's /proc and /sys are
+pub const LIVE_ROOT: &str = "/";
+
+/// where the meters and the process table get their numbers from, and where
+/// kills, renices and so on go
+pub trait MetricsSource {
+    /// reads everything again
+    fn refresh(&mut self, options: &SnapshotOptions) -> io::Result<Snapshot>;
+
+    /// which machine this is, for the table title. None for this one
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// whether the processes can be signalled, reniced and so on. if not, the
+    /// methods below just say so
+    fn can_change_processes(&self) -> bool {
+        false
+    }
+
+    /// signals the whole process, or only one thread of it if given a tid
+    fn send_signal(&mut self, _pid: i32, _tid: Option<i32>, _signal: i32) -> Result<(), String> {
+        Err(cant_change())
+    }
+
+    /// moves the nice value by `change`, so -1 raises the priority. without a
+    /// tid, every thread of the process is moved
+    fn renice(&mut self, _pid: i32, _tid: Option<i32>, _change: i64) -> Result<(), String> {
+        Err(cant_change())
+    }
+
+    fn get_affinity(
+        &mut self,
+        _pid: i32,
+        _tid: Option<i32>,
+        _num_cpus: usize,
+    ) -> Result<Vec<bool>, String> {
+        Err(cant_change())
+    }
+
+    /// without a tid, every thread of the process is pinned
+    fn set_affinity(&mut self, _pid: i32, _tid: Option<i32>, _cpus: &[bool]) -> Result<(), String> {
+        Err(cant_change())
+    }
+}
+
+fn cant_change() -> String {
+    String::from("these processes can't be changed from here")
+}
+
+/// what has to be in a snapshot's process list
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SnapshotOptions {
+    pub show_threads: bool,
+    /// fill in the fields from /proc/[pid]/stat
+    pub read_proc_stat: bool,
+    /// fill in the allowed cpus, from /proc/[pid]/status
+    pub read_affinity: bool,
+}
+
+impl SnapshotOptions {
+    /// only what the table needs, unless `read_all_fields` is set
+    pub fn new(app_state: &AppState) -> Self {
+        let all = app_state.read_all_fields;
+        let shown = |kind: ColumnType| {
+            app_state.headers.iter().any(|c| c.kind == kind) || app_state.sorting_by == Some(kind)
+        };
+        Self {
+            show_threads: app_state.show_threads,
+            read_proc_stat: all
+                || app_state.headers.iter().any(|c| c.kind.needs_proc_stat())
+                || matches!(app_state.sorting_by, Some(key) if key.needs_proc_stat()),
+            read_affinity: all || shown(ColumnType::AFFINITY),
+        }
+    }
+}
+
+/// the readers that keep the last values around to work out rates. both
+/// sources use these, pointed at different roots
+pub struct KernelStats {
+    cpu_stat: CpuStat,
+    vminfo: vmstat_info,
+    zswap: ZswapMonitor,
+}
+
+impl KernelStats {
+    pub fn new() -> Self {
+        Self {
+            cpu_stat: CpuStat::new(),
+            vminfo: vmstat_info::new(),
+            zswap: ZswapMonitor::new(),
+        }
+    }
+
+    pub fn update(&mut self, root: &Path, zswap_source: Option<&mut ZswapSource>) {
+        // rates are worked out over the uptime of the /proc they're read from,
+        // not over the time between refreshes
+        let uptime = read_uptime(root).unwrap_or(0.0);
+        self.cpu_stat.update(root);
+        self.vminfo.update(root, uptime);
+        self.zswap.update(root, zswap_source, uptime);
+    }
+
+    /// everything but uptime, load, cpu usage and memory/swap use, which
+    /// sysinfo gives us on a live system
+    pub fn system_record(&self, root: &Path, zswap_source: Option<&ZswapSource>) -> SystemRecord {
+        SystemRecord {
+            cpu: self.cpu_stat.total,
+            cores: self.cpu_stat.cores.clone(),
+            memory: meminfo::read_meminfo(root).map(|m| m.breakdown()),
+            vmstat: self.vminfo.rates,
+            pressure: psi::read_pressure(root),
+            zswap: self.zswap.stats,
+            zswap_rates: self.zswap.stats.map(|_| self.zswap.rates),
+            zswap_parameters: self.zswap.parameters.clone(),
+            zswap_source: zswap_source.map(|source| match source {
+                ZswapSource::Debugfs(dir) => format!("debugfs at {}", dir.display()),
+                ZswapSource::Helper(_) => String::from("debugfs read by the sudo helper"),
+            }),
+            zswap_error: self.zswap.error.as_ref().map(|e| e.to_string()),
+            zram: zram::read_zram_devices(root),
+            ..SystemRecord::default()
+        }
+    }
+}
+
+impl Default for KernelStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// this machine, through sysinfo and /proc
+pub struct LiveSource {
+    sys: System,
+    stats: KernelStats,
+    /// how zswap stats are read, if they can be
+    zswap_source: Option<ZswapSource>,
+    user_names: HashMap<u32, String>,
+}
+
+impl LiveSource {
+    pub fn new(zswap_source: Option<ZswapSource>) -> Self {
+        let mut sys = System::new_all();
+        sys.refresh_all();
+        Self {
+            sys,
+            stats: KernelStats::new(),
+            zswap_source,
+            user_names: proc_stat::read_user_names(Path::new(LIVE_ROOT)),
+        }
+    }
+}
+
+impl MetricsSource for LiveSource {
+    fn refresh(&mut self, options: &SnapshotOptions) -> io::Result<Snapshot> {
+        let root = Path::new(LIVE_ROOT);
+        let sys = &mut self.sys;
+        sys.refresh_cpu();
+        sys.refresh_processes();
+        sys.refresh_memory();
+        self.stats.update(root, self.zswap_source.as_mut());
+        let sys = &self.sys;
+        let load = sys.get_load_average();
+        let system = SystemRecord {
+            uptime: sys.get_uptime(),
+            load_average: [load.one, load.five, load.fifteen],
+            cpu_percent: sys.get_global_processor_info().get_cpu_usage(),
+            cpu_system_percent: sys.get_global_processor_info().get_system_percent(),
+            per_cpu_percent: sys
+                .get_processors()
+                .iter()
+                .map(|p| p.get_cpu_usage())
+                .collect(),
+            memory_used: sys.get_used_memory(),
+            memory_total: sys.get_total_memory(),
+            swap_used: sys.get_used_swap(),
+            swap_total: sys.get_total_swap(),
+            ..self.stats.system_record(root, self.zswap_source.as_ref())
+        };
+        Ok(Snapshot {
+            time: now(),
+            system,
+            processes: processes::get_process_records(
+                sys.get_processes(),
+                options,
+                &self.user_names,
+            ),
+        })
+    }
+
+    fn can_change_processes(&self) -> bool {
+        true
+    }
+
+    fn send_signal(&mut self, pid: i32, tid: Option<i32>, signal: i32) -> Result<(), String> {
+        process_actions::send_signal(pid, tid, signal)
+    }
+
+    fn renice(&mut self, pid: i32, tid: Option<i32>, change: i64) -> Result<(), String> {
+        let dir = proc_stat::process_dir(Path::new(LIVE_ROOT), tid.unwrap_or(pid));
+        let nice = proc_stat::read_proc_stat(&dir).map_or(0, |s| s.nice);
+        process_actions::renice(&live_tasks(pid, tid)?, nice.saturating_add(change))
+    }
+
+    fn get_affinity(
+        &mut self,
+        pid: i32,
+        tid: Option<i32>,
+        num_cpus: usize,
+    ) -> Result<Vec<bool>, String> {
+        process_actions::get_affinity(tid.unwrap_or(pid), num_cpus)
+    }
+
+    fn set_affinity(&mut self, pid: i32, tid: Option<i32>, cpus: &[bool]) -> Result<(), String> {
+        process_actions::set_affinity(&live_tasks(pid, tid)?, cpus)
+    }
+}
+
+/// the thread `tid`, or every thread of the process from /proc/<pid>/task
+fn live_tasks(pid: i32, tid: Option<i32>) -> Result<Vec<i32>, String> {
+    if let Some(tid) = tid {
+        return Ok(vec![tid]);
+    }
+    let dir = proc_stat::process_dir(Path::new(LIVE_ROOT), pid).join("task");
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(String::from("no such process"))
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect())
+}
+
+/// seconds since boot, from /proc/uptime under `root`
+pub fn read_uptime(root: &Path) -> Option<f64> {
+    fs::read_to_string(root.join("proc/uptime"))
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -5,6 +5,7 @@ pub mod event;
 
 use rand::distributions::{Distribution, Uniform};
 use rand::rngs::ThreadRng;
+use tui::layout::Rect;
 use tui::text::Spans;
 use tui::widgets::ListState;
 use tui::{
@@ -182,4 +183,36 @@ impl StatefulTable<'_> {
         };
         self.state.select(Some(i));
     }
-}
\ No newline at end of file
+}
+/// a rect of (at most) the given size in the middle of `area`, for popups
+pub fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// "90s", "5m", "2h30m"
+pub fn format_seconds(seconds: u64) -> String {
+    match seconds {
+        s if s < 120 => format!("{}s", s),
+        s if s < 7200 => format!("{}m", s / 60),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s => format!("{}h{}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+/// HH:MM:SS in local time, for a time in seconds since the unix epoch
+pub fn format_clock_time(time: u64) -> String {
+    let time = time as libc::time_t;
+    // localtime_r fills in tm, and doesn't touch any shared state
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return String::from("??:??:??");
+    }
+    format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
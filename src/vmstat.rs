@@ -1,62 +1,123 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    process::exit,
-    usize,
-};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::Path};
 
-use regex::Regex;
+/// every counter in /proc/vmstat at one point in time
+#[derive(Clone)]
+pub struct VmStatSnapshot {
+    counters: HashMap<String, u64>,
+    /// seconds since boot
+    taken: f64,
+}
+
+impl VmStatSnapshot {
+    /// `root` is / for this machine. `uptime` is from the same /proc, so a
+    /// captured one gets rates over the time between the captures
+    pub fn read(root: &Path, uptime: f64) -> io::Result<Self> {
+        let contents = fs::read_to_string(root.join("proc/vmstat"))?;
+        Ok(Self::parse(&contents, uptime))
+    }
+
+    /// "name value" per line. anything else is skipped
+    pub fn parse(contents: &str, taken: f64) -> Self {
+        let counters = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let name = fields.next()?;
+                let value = fields.next()?.parse().ok()?;
+                Some((name.to_string(), value))
+            })
+            .collect();
+        Self { counters, taken }
+    }
+
+    /// None if this kernel doesn't have the counter
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.counters.get(name).copied()
+    }
+
+    /// the sum of whichever of `names` exist
+    fn sum(&self, names: &[&str]) -> u64 {
+        names.iter().filter_map(|name| self.get(name)).sum()
+    }
+}
+
+// the reclaim counters are split up by who did the work
+const PGSCAN: [&str; 3] = ["pgscan_kswapd", "pgscan_direct", "pgscan_khugepaged"];
+const PGSTEAL: [&str; 3] = ["pgsteal_kswapd", "pgsteal_direct", "pgsteal_khugepaged"];
+
+/// per second rates between two snapshots, plus the oom kills in between
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct VmStatRates {
+    /// pages swapped in/out
+    pub swap_in: f64,
+    pub swap_out: f64,
+    /// KB paged in/out from disk, including swap
+    pub page_in: f64,
+    pub page_out: f64,
+    pub faults: f64,
+    pub major_faults: f64,
+    /// pages scanned and reclaimed by kswapd and direct reclaim
+    pub scanned: f64,
+    pub stolen: f64,
+    /// not a rate - how many processes were oom killed since the last snapshot
+    pub oom_kills: u64,
+    pub oom_kills_total: u64,
+    /// transparent huge pages allocated on fault/by khugepaged, and split back up
+    pub thp_alloc: f64,
+    pub thp_split: f64,
+}
+
+impl VmStatRates {
+    pub fn between(old: &VmStatSnapshot, new: &VmStatSnapshot) -> Self {
+        let elapsed = new.taken - old.taken;
+        if elapsed <= 0.0 {
+            return Self::default();
+        }
+        let delta = |names: &[&str]| new.sum(names).saturating_sub(old.sum(names));
+        let rate = |names: &[&str]| delta(names) as f64 / elapsed;
+        Self {
+            swap_in: rate(&["pswpin"]),
+            swap_out: rate(&["pswpout"]),
+            page_in: rate(&["pgpgin"]),
+            page_out: rate(&["pgpgout"]),
+            faults: rate(&["pgfault"]),
+            major_faults: rate(&["pgmajfault"]),
+            scanned: rate(&PGSCAN),
+            stolen: rate(&PGSTEAL),
+            oom_kills: delta(&["oom_kill"]),
+            oom_kills_total: new.get("oom_kill").unwrap_or(0),
+            thp_alloc: rate(&["thp_fault_alloc", "thp_collapse_alloc"]),
+            thp_split: rate(&["thp_split_page"]),
+        }
+    }
+}
 
 pub struct vmstat_info {
-    pub swap_in: usize,
-    pub swap_out: usize,
-    pub swap_in_last: usize,
-    pub swap_out_last: usize,
+    /// the most recent snapshot, None until the first successful read
+    pub current: Option<VmStatSnapshot>,
+    /// rates between the last two snapshots
+    pub rates: VmStatRates,
 }
 
 impl vmstat_info {
     pub fn new() -> Self {
         Self {
-            swap_in: 0,
-            swap_out: 0,
-            swap_in_last: 0,
-            swap_out_last: 0,
+            current: None,
+            rates: VmStatRates::default(),
         }
     }
 
-    pub fn update(&mut self) {
-        let file = File::open("/proc/vmstat").unwrap();
-        let reader = BufReader::new(file);
-        let re = Regex::new(r"([^ ]+) ([0-9]+)").unwrap();
-
-        // Read the file line by line using the lines() iterator from std::io::BufRead.
-        for (_index, line) in reader.lines().enumerate() {
-            let line = line.unwrap(); // Ignore errors.
-            let mut captures = re.captures(&line);
-            if let Some(captures) = captures {
-                if captures.len() != 3 {
-                    continue;
-                }
-                let field = &captures[1];
-                let parsed_value = captures[2].parse();
-                if parsed_value.is_ok() {
-                    let value = parsed_value.ok();
-                    if let Some(value) = value {
-                        match field {
-                            "pswpin" => {
-                                self.swap_in = value - self.swap_in_last;
-                                self.swap_in_last = value;
-                            }
-                            "pswpout" => {
-                                self.swap_out = value - self.swap_out_last;
-                                self.swap_out_last = value;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
+    pub fn update(&mut self, root: &Path, uptime: f64) {
+        // if it can't be read, keep showing the last rates
+        let snapshot = match VmStatSnapshot::read(root, uptime) {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        if let Some(last) = &self.current {
+            self.rates = VmStatRates::between(last, &snapshot);
         }
+        self.current = Some(snapshot);
     }
 }
 
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// one /sys/block/zramN device. sizes are in bytes, writeback counts in pages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZramDevice {
+    pub name: String,
+    /// the one in [brackets] in comp_algorithm
+    pub algorithm: String,
+    pub disk_size: u64,
+    // mm_stat
+    pub orig_data_size: u64,
+    pub compr_data_size: u64,
+    pub mem_used_total: u64,
+    pub same_pages: u64,
+    pub huge_pages: u64,
+    // io_stat
+    pub failed_reads: u64,
+    pub failed_writes: u64,
+    // bd_stat, only there if the kernel has CONFIG_ZRAM_WRITEBACK
+    pub bd_count: u64,
+    pub bd_reads: u64,
+    pub bd_writes: u64,
+}
+
+impl ZramDevice {
+    /// None if nothing has been stored yet
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.compr_data_size {
+            0 => None,
+            compressed => Some(self.orig_data_size as f64 / compressed as f64),
+        }
+    }
+}
+
+const BLOCK_DIR: &str = "sys/block";
+
+/// every zram device that's been set up, sorted by name. empty if there are
+/// none. `root` is / for this machine
+pub fn read_zram_devices(root: &Path) -> Vec<ZramDevice> {
+    let entries = match fs::read_dir(root.join(BLOCK_DIR)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut devices: Vec<ZramDevice> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match name.starts_with("zram") {
+                true => read_zram_device(&name, &entry.path()),
+                false => None,
+            }
+        })
+        // a device with no disksize hasn't been set up, and can't be swapped to
+        .filter(|device| device.disk_size > 0)
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+fn read_zram_device(name: &str, dir: &Path) -> Option<ZramDevice> {
+    let read = |file: &str| fs::read_to_string(dir.join(file)).ok();
+    let mm_stat = read_fields(&read("mm_stat")?);
+    let io_stat = read("io_stat").map(|s| read_fields(&s)).unwrap_or_default();
+    let bd_stat = read("bd_stat").map(|s| read_fields(&s)).unwrap_or_default();
+    let field = |fields: &[u64], i: usize| fields.get(i).copied().unwrap_or(0);
+    Some(ZramDevice {
+        name: name.to_string(),
+        algorithm: read("comp_algorithm")
+            .and_then(|s| selected_algorithm(&s))
+            .unwrap_or_else(|| String::from("?")),
+        disk_size: read("disksize")
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0),
+        orig_data_size: field(&mm_stat, 0),
+        compr_data_size: field(&mm_stat, 1),
+        mem_used_total: field(&mm_stat, 2),
+        same_pages: field(&mm_stat, 5),
+        huge_pages: field(&mm_stat, 7),
+        failed_reads: field(&io_stat, 0),
+        failed_writes: field(&io_stat, 1),
+        bd_count: field(&bd_stat, 0),
+        bd_reads: field(&bd_stat, 1),
+        bd_writes: field(&bd_stat, 2),
+    })
+}
+
+/// whitespace separated numbers, like mm_stat. anything that isn't a number is 0
+fn read_fields(contents: &str) -> Vec<u64> {
+    contents
+        .split_whitespace()
+        .map(|field| field.parse().unwrap_or(0))
+        .collect()
+}
+
+/// "lzo lzo-rle [zstd]" -> "zstd"
+fn selected_algorithm(contents: &str) -> Option<String> {
+    contents
+        .split_whitespace()
+        .find(|a| a.starts_with('['))
+        .map(|a| a.trim_matches(|c| c == '[' || c == ']').to_string())
+}
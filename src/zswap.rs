@@ -1,6 +1,12 @@
-use std::{error::Error, fs};
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
 
-use crate::debug_permissions::DEBUG_DIR;
+use serde::{Deserialize, Serialize};
+
+use crate::zswap_helper::ZswapHelper;
 
 /*
 same_filled_pages
@@ -15,7 +21,7 @@ reject_reclaim_fail
 pool_limit_hit
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct ZswapStats {
     pub same_filled_pages: u64,
     pub stored_pages: u64,
@@ -44,25 +50,218 @@ impl ZswapStats {
             pool_limit_hit: 0,
         }
     }
+
+    /// how much smaller the stored pages are in the pool, e.g. 3.0 for 3:1. None when empty
+    pub fn compression_ratio(&self, page_size: u64) -> Option<f64> {
+        match self.pool_total_size {
+            0 => None,
+            pool => Some((self.stored_pages * page_size) as f64 / pool as f64),
+        }
+    }
+
+    /// share of stored pages that were a single repeated value (usually zero),
+    /// and take no pool space at all
+    pub fn same_filled_ratio(&self) -> Option<f64> {
+        match self.stored_pages {
+            0 => None,
+            stored => Some(self.same_filled_pages as f64 / stored as f64),
+        }
+    }
+}
+
+/// per second rates of the zswap event counters
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ZswapRates {
+    pub written_back_pages: f64,
+    pub reject_compress_poor: f64,
+    pub reject_kmemcache_fail: f64,
+    pub reject_alloc_fail: f64,
+    pub reject_reclaim_fail: f64,
+    pub pool_limit_hit: f64,
 }
-pub fn read_zswap_stats() -> Result<ZswapStats, &'static str> {
+
+impl ZswapRates {
+    fn between(old: &ZswapStats, new: &ZswapStats, seconds: f64) -> Self {
+        if seconds <= 0.0 {
+            return Self::default();
+        }
+        let rate = |old: u64, new: u64| new.saturating_sub(old) as f64 / seconds;
+        Self {
+            written_back_pages: rate(old.written_back_pages, new.written_back_pages),
+            reject_compress_poor: rate(old.reject_compress_poor, new.reject_compress_poor),
+            reject_kmemcache_fail: rate(old.reject_kmemcache_fail, new.reject_kmemcache_fail),
+            reject_alloc_fail: rate(old.reject_alloc_fail, new.reject_alloc_fail),
+            reject_reclaim_fail: rate(old.reject_reclaim_fail, new.reject_reclaim_fail),
+            pool_limit_hit: rate(old.pool_limit_hit, new.pool_limit_hit),
+        }
+    }
+}
+
+/// the module parameters. unlike the stats, these don't need debugfs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZswapParameters {
+    pub enabled: bool,
+    pub compressor: String,
+    pub zpool: String,
+    pub max_pool_percent: u64,
+}
+
+const PARAMETERS_DIR: &str = "sys/module/zswap/parameters";
+
+/// None if zswap isn't built into this kernel. `root` is / for this machine
+pub fn read_zswap_parameters(root: &Path) -> Option<ZswapParameters> {
+    let dir = root.join(PARAMETERS_DIR);
+    let read = |name: &str| {
+        fs::read_to_string(dir.join(name))
+            .map(|s| s.trim().to_string())
+            .ok()
+    };
+    Some(ZswapParameters {
+        enabled: read("enabled")? == "Y",
+        compressor: read("compressor").unwrap_or_else(|| String::from("?")),
+        zpool: read("zpool").unwrap_or_else(|| String::from("?")),
+        max_pool_percent: read("max_pool_percent")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0),
+    })
+}
+
+#[derive(Debug)]
+pub enum ZswapError {
+    /// there's no zswap directory in debugfs - zswap isn't loaded, or has never been used
+    ModuleMissing(PathBuf),
+    PermissionDenied(PathBuf),
+    /// a stat file that isn't a number
+    Parse {
+        path: PathBuf,
+        value: String,
+    },
+    /// any other read error, e.g. a file disappearing
+    Io(PathBuf, io::Error),
+    /// from the privileged helper, or talking to it
+    Helper(String),
+}
+
+impl fmt::Display for ZswapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZswapError::ModuleMissing(dir) => {
+                write!(f, "no zswap stats in {} - is zswap enabled?", dir.display())
+            }
+            ZswapError::PermissionDenied(path) => {
+                write!(f, "permission denied reading {}", path.display())
+            }
+            ZswapError::Parse { path, value } => {
+                write!(f, "could not parse `{}` from {}", value, path.display())
+            }
+            ZswapError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ZswapError::Helper(message) => write!(f, "helper: {}", message),
+        }
+    }
+}
+
+impl Error for ZswapError {}
+
+/// where the stats come from
+pub enum ZswapSource {
+    /// debugfs is readable, so just read it
+    Debugfs(PathBuf),
+    /// ask `ktop --zswap-helper`, running as root
+    Helper(ZswapHelper),
+}
+
+impl ZswapSource {
+    pub fn read_stats(&mut self) -> Result<ZswapStats, ZswapError> {
+        match self {
+            ZswapSource::Debugfs(dir) => read_zswap_stats(dir),
+            ZswapSource::Helper(helper) => helper.read_stats(),
+        }
+    }
+}
+
+/// everything the zswap panel shows, kept between refreshes to work out rates
+pub struct ZswapMonitor {
+    pub stats: Option<ZswapStats>,
+    pub rates: ZswapRates,
+    pub parameters: Option<ZswapParameters>,
+    /// why the stats couldn't be read last time, if they couldn't
+    pub error: Option<ZswapError>,
+    /// seconds since boot at the last read
+    last_read: Option<f64>,
+}
+
+impl ZswapMonitor {
+    pub fn new() -> Self {
+        Self {
+            stats: None,
+            rates: ZswapRates::default(),
+            parameters: None,
+            error: None,
+            last_read: None,
+        }
+    }
+
+    /// the stats are only read if there's a way to read debugfs. on an error, the
+    /// old stats are dropped rather than shown as if they were current
+    /// the parameters are read from under `root`, which is / for this machine
+    pub fn update(&mut self, root: &Path, source: Option<&mut ZswapSource>, uptime: f64) {
+        self.parameters = read_zswap_parameters(root);
+        let source = match source {
+            Some(source) => source,
+            None => return,
+        };
+        let stats = match source.read_stats() {
+            Ok(s) => s,
+            Err(e) => {
+                self.stats = None;
+                self.rates = ZswapRates::default();
+                self.last_read = None;
+                self.error = Some(e);
+                return;
+            }
+        };
+        self.error = None;
+        if let (Some(old), Some(last_read)) = (&self.stats, self.last_read) {
+            self.rates = ZswapRates::between(old, &stats, uptime - last_read);
+        }
+        self.stats = Some(stats);
+        self.last_read = Some(uptime);
+    }
+}
+
+impl Default for ZswapMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+pub fn read_zswap_stats(debug_dir: &Path) -> Result<ZswapStats, ZswapError> {
+    let dir = debug_dir.join("zswap");
+    if !dir.exists() {
+        return Err(ZswapError::ModuleMissing(dir));
+    }
     let mut stats = ZswapStats::new();
-    stats.same_filled_pages = get_zswap_stat("same_filled_pages");
-    stats.stored_pages = get_zswap_stat("stored_pages");
-    stats.pool_total_size = get_zswap_stat("pool_total_size");
-    stats.duplicate_entry = get_zswap_stat("duplicate_entry");
-    stats.written_back_pages = get_zswap_stat("written_back_pages");
-    stats.reject_compress_poor = get_zswap_stat("reject_compress_poor");
-    stats.reject_kmemcache_fail = get_zswap_stat("reject_kmemcache_fail");
-    stats.reject_alloc_fail = get_zswap_stat("reject_alloc_fail");
-    stats.reject_reclaim_fail = get_zswap_stat("reject_reclaim_fail");
-    stats.pool_limit_hit = get_zswap_stat("pool_limit_hit");
+    stats.same_filled_pages = get_zswap_stat(&dir, "same_filled_pages")?;
+    stats.stored_pages = get_zswap_stat(&dir, "stored_pages")?;
+    stats.pool_total_size = get_zswap_stat(&dir, "pool_total_size")?;
+    stats.duplicate_entry = get_zswap_stat(&dir, "duplicate_entry")?;
+    stats.written_back_pages = get_zswap_stat(&dir, "written_back_pages")?;
+    stats.reject_compress_poor = get_zswap_stat(&dir, "reject_compress_poor")?;
+    stats.reject_kmemcache_fail = get_zswap_stat(&dir, "reject_kmemcache_fail")?;
+    stats.reject_alloc_fail = get_zswap_stat(&dir, "reject_alloc_fail")?;
+    stats.reject_reclaim_fail = get_zswap_stat(&dir, "reject_reclaim_fail")?;
+    stats.pool_limit_hit = get_zswap_stat(&dir, "pool_limit_hit")?;
     Ok(stats)
 }
 
-fn get_zswap_stat(name: &str) ->u64{
-        fs::read_to_string(format!("{}{}{}", DEBUG_DIR, "/zswap/", name))
-            .expect("0").replace('\n', "")
-            .parse()
-            .unwrap_or(0)
+fn get_zswap_stat(dir: &Path, name: &str) -> Result<u64, ZswapError> {
+    let path = dir.join(name);
+    let contents = fs::read_to_string(&path).map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => ZswapError::PermissionDenied(path.clone()),
+        _ => ZswapError::Io(path.clone(), e),
+    })?;
+    let value = contents.trim();
+    value.parse().map_err(|_| ZswapError::Parse {
+        path: path.clone(),
+        value: value.to_string(),
+    })
 }
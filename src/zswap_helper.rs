@@ -0,0 +1,177 @@
+//! debugfs is usually only readable by root, so instead of running all of ktop
+//! as root (or opening debugfs up to everyone), a second copy of ktop runs as
+//! `ktop --zswap-helper` under sudo. all it can do is read the zswap stat files
+//! and write them to its stdout, one line per request from the UI:
+//!
+//! helper: `ready` once it's started, or `err <message>` if it can't
+//! ui:     `read`
+//! helper: `ok <same_filled_pages> <stored_pages> ... <pool_limit_hit>` or `err <message>`
+//!
+//! the helper exits when its stdin is closed, i.e. when the UI exits
+
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use crate::debug_permissions::{find_debugfs, is_debugfs_mount, DEFAULT_DEBUG_DIR};
+use crate::zswap::{read_zswap_stats, ZswapError, ZswapStats};
+
+/// the UI's end of the pipe
+pub struct ZswapHelper {
+    child: Child,
+    /// only None while dropping
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ZswapHelper {
+    /// starts `sudo ktop --zswap-helper` and waits for it to be ready. sudo asks
+    /// for a password on the terminal, so this has to happen before the UI starts.
+    /// the helper reads the same debugfs the UI checked
+    pub fn spawn(debugfs_path: &Path) -> io::Result<Self> {
+        let mut child = Command::new("sudo")
+            .arg(env::current_exe()?)
+            .arg("--zswap-helper")
+            .arg("--debugfs-path")
+            .arg(debugfs_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (Some(stdin), BufReader::new(stdout)),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "no pipe to the helper",
+                ))
+            }
+        };
+        let mut helper = Self {
+            child,
+            stdin,
+            stdout,
+        };
+        let line = helper.read_line()?;
+        match line.split_once(' ') {
+            _ if line == "ready" => Ok(helper),
+            Some(("err", message)) => Err(io::Error::new(io::ErrorKind::Other, message)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the helper didn't start - was the sudo password right?",
+            )),
+        }
+    }
+
+    pub fn read_stats(&mut self) -> Result<ZswapStats, ZswapError> {
+        let lost = |e: io::Error| ZswapError::Helper(format!("lost the helper: {}", e));
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return Err(ZswapError::Helper(String::from("the helper was stopped"))),
+        };
+        writeln!(stdin, "read").map_err(lost)?;
+        stdin.flush().map_err(lost)?;
+        let line = self.read_line().map_err(lost)?;
+        match line.split_once(' ') {
+            Some(("ok", values)) => parse_stats(values)
+                .ok_or_else(|| ZswapError::Helper(format!("bad reply from the helper: {}", line))),
+            Some(("err", message)) => Err(ZswapError::Helper(message.to_string())),
+            _ => Err(ZswapError::Helper(format!(
+                "bad reply from the helper: {}",
+                line
+            ))),
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the helper exited",
+            ));
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+impl Drop for ZswapHelper {
+    fn drop(&mut self) {
+        // it runs as root, so it can't be killed - closing the pipe tells it to exit
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+
+/// the helper's main loop, for `ktop --zswap-helper`. reads from `debugfs_path`
+/// if given, or wherever debugfs is mounted
+pub fn run(debugfs_path: Option<&Path>) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let debug_dir = match debugfs_path {
+        // the helper runs as root, so it only reads from a real debugfs mount,
+        // not any directory it's pointed at
+        Some(path) if !is_debugfs_mount(path) => {
+            writeln!(out, "err debugfs isn't mounted at {}", path.display())?;
+            return Ok(());
+        }
+        Some(path) => path.to_path_buf(),
+        None => find_debugfs().unwrap_or_else(|| PathBuf::from(DEFAULT_DEBUG_DIR)),
+    };
+    writeln!(out, "ready")?;
+    out.flush()?;
+    for line in io::stdin().lock().lines() {
+        match line?.as_str() {
+            "read" => match read_zswap_stats(&debug_dir) {
+                Ok(stats) => writeln!(out, "ok {}", format_stats(&stats))?,
+                Err(e) => writeln!(out, "err {}", e)?,
+            },
+            other => writeln!(out, "err unknown request `{}`", other)?,
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn format_stats(stats: &ZswapStats) -> String {
+    format!(
+        "{} {} {} {} {} {} {} {} {} {}",
+        stats.same_filled_pages,
+        stats.stored_pages,
+        stats.pool_total_size,
+        stats.duplicate_entry,
+        stats.written_back_pages,
+        stats.reject_compress_poor,
+        stats.reject_kmemcache_fail,
+        stats.reject_alloc_fail,
+        stats.reject_reclaim_fail,
+        stats.pool_limit_hit
+    )
+}
+
+/// the other end of `format_stats`
+fn parse_stats(line: &str) -> Option<ZswapStats> {
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    match values[..] {
+        [same_filled_pages, stored_pages, pool_total_size, duplicate_entry, written_back_pages, reject_compress_poor, reject_kmemcache_fail, reject_alloc_fail, reject_reclaim_fail, pool_limit_hit] => {
+            Some(ZswapStats {
+                same_filled_pages,
+                stored_pages,
+                pool_total_size,
+                duplicate_entry,
+                written_back_pages,
+                reject_compress_poor,
+                reject_kmemcache_fail,
+                reject_alloc_fail,
+                reject_reclaim_fail,
+                pool_limit_hit,
+            })
+        }
+        _ => None,
+    }
+}
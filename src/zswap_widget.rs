@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Widget},
+};
+
+use crate::snapshot::SystemRecord;
+use crate::zswap::ZswapStats;
+
+/// lines of text inside the panel, not counting the border
+pub const ZSWAP_PANEL_LINES: u16 = 4;
+
+/// compression, rejects and writeback for zswap, plus how it's set up
+pub struct ZswapWidget<'a> {
+    pub system: &'a SystemRecord,
+    /// where the stats would be read from with --zswap
+    pub debugfs_path: &'a Path,
+    pub page_size: u64,
+}
+
+impl<'a> Widget for ZswapWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let system = self.system;
+        let title = match &system.zswap_parameters {
+            Some(p) => format!(
+                "zswap ({}, {}/{}, max pool {}%)",
+                match p.enabled {
+                    true => "enabled",
+                    false => "disabled",
+                },
+                p.compressor,
+                p.zpool,
+                p.max_pool_percent
+            ),
+            None => String::from("zswap"),
+        };
+        let title = match (&system.zswap, &system.zswap_source) {
+            (Some(_), Some(source)) => format!("{} - {}", title, source),
+            _ => title,
+        };
+        let text = match (&system.zswap_parameters, &system.zswap, &system.zswap_error) {
+            (None, _, _) => vec![Spans::from("zswap isn't available on this kernel")],
+            (Some(_), Some(stats), _) => self.stats_lines(stats),
+            (Some(_), None, Some(e)) => vec![Spans::from(Span::styled(
+                e.clone(),
+                Style::default().fg(Color::Red),
+            ))],
+            (Some(_), None, None) => vec![Spans::from(format!(
+                "the rest of the stats are in debugfs ({}) - run with --zswap to read them",
+                self.debugfs_path.display()
+            ))],
+        };
+        Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .render(area, buf);
+    }
+}
+
+impl<'a> ZswapWidget<'a> {
+    fn stats_lines(&self, stats: &ZswapStats) -> Vec<Spans<'static>> {
+        let rates = self.system.zswap_rates.unwrap_or_default();
+        let size = |bytes: u64| bytefmt::format_base2(bytes).replace('B', "");
+        let ratio = match stats.compression_ratio(self.page_size) {
+            Some(r) => format!("{:.2}x", r),
+            None => String::from("-"),
+        };
+        let same_filled = match stats.same_filled_ratio() {
+            Some(r) => format!("{:.1}%", r * 100.0),
+            None => String::from("-"),
+        };
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        // anything being rejected or hitting the limit is worth noticing
+        let warn = |rate: f64| match rate > 0.0 {
+            true => Style::default().fg(Color::Red),
+            false => Style::default(),
+        };
+        let rejected = rates.reject_compress_poor
+            + rates.reject_kmemcache_fail
+            + rates.reject_alloc_fail
+            + rates.reject_reclaim_fail;
+        vec![
+            Spans::from(vec![
+                Span::styled("Stored ", heading),
+                Span::raw(format!(
+                    "{} in a {} pool, ratio {}  same-filled {}  duplicates {}",
+                    size(stats.stored_pages * self.page_size),
+                    size(stats.pool_total_size),
+                    ratio,
+                    same_filled,
+                    stats.duplicate_entry
+                )),
+            ]),
+            Spans::from(vec![
+                Span::styled("Written back ", heading),
+                Span::raw(format!(
+                    "{} pages, {:.1}/s  ",
+                    stats.written_back_pages, rates.written_back_pages
+                )),
+                Span::styled("Pool limit hit ", heading),
+                Span::styled(
+                    format!("{} times, {:.1}/s", stats.pool_limit_hit, rates.pool_limit_hit),
+                    warn(rates.pool_limit_hit),
+                ),
+            ]),
+            Spans::from(vec![
+                Span::styled("Rejects/s ", heading),
+                Span::styled(
+                    format!(
+                        "compress poor {:.1}  kmemcache fail {:.1}  alloc fail {:.1}  reclaim fail {:.1}",
+                        rates.reject_compress_poor,
+                        rates.reject_kmemcache_fail,
+                        rates.reject_alloc_fail,
+                        rates.reject_reclaim_fail
+                    ),
+                    warn(rejected),
+                ),
+            ]),
+            Spans::from(vec![
+                Span::styled("Rejects   ", heading),
+                Span::raw(format!(
+                    "compress poor {}  kmemcache fail {}  alloc fail {}  reclaim fail {}",
+                    stats.reject_compress_poor,
+                    stats.reject_kmemcache_fail,
+                    stats.reject_alloc_fail,
+                    stats.reject_reclaim_fail
+                )),
+            ]),
+        ]
+    }
+}